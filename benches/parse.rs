@@ -0,0 +1,146 @@
+//! Compares decoding a response into an owned [`Message`] with reading it in place as a [`MessageRef`].
+//!
+//! Run with `cargo bench`; the allocations each parser makes per message are printed first.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io::Cursor,
+    net::Ipv4Addr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dirt::{
+    dname::DomainName,
+    header::{Flags, Header},
+    message::{Message, MessageRef, MsgSection},
+    qclass::QClass,
+    qtype::QType,
+    question::Question,
+    rdata::RData,
+    record::{Edns, Record},
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// A referral-sized response: a question, a CNAME, a few addresses, name servers and glue
+fn response() -> Vec<u8> {
+    let record = |name: &str, rdata: RData| Record {
+        name: DomainName::new(name),
+        qtype: rdata.rtype(),
+        class: QClass::IN,
+        time_to_live: 3600,
+        rdata,
+    };
+
+    let answers = std::iter::once(record(
+        "www.example.com",
+        RData::CNAME(DomainName::new("web.example.com")),
+    ))
+    .chain((1..=4).map(|host| {
+        let addr = Ipv4Addr::new(192, 0, 2, host);
+        record("web.example.com", RData::A(addr))
+    }))
+    .collect();
+    let authorities = ["a", "b", "c", "d"]
+        .map(|ns| {
+            record(
+                "example.com",
+                RData::NS(DomainName::new(&format!("{ns}.iana-servers.net"))),
+            )
+        })
+        .to_vec();
+    let additionals = ["a", "b", "c", "d"]
+        .into_iter()
+        .zip(1..)
+        .map(|(ns, host)| {
+            record(
+                &format!("{ns}.iana-servers.net"),
+                RData::A(Ipv4Addr::new(198, 51, 100, host)),
+            )
+        })
+        .collect();
+
+    Message {
+        header: Header {
+            id: 0xbeef,
+            flags: Flags::new().with_response(true),
+            num_questions: 1,
+            num_answers: 0,
+            num_authorities: 0,
+            num_additionals: 0,
+        },
+        questions: vec![Question {
+            qname: DomainName::new("www.example.com"),
+            qtype: QType::A,
+            qclass: QClass::IN,
+        }],
+        answers,
+        authorities,
+        additionals,
+        edns: Some(Edns::default()),
+    }
+    .to_bytes()
+    .unwrap()
+}
+
+fn parse_owned(bytes: &[u8]) -> usize {
+    let message = Message::from_bytes(&mut Cursor::new(bytes)).unwrap();
+    message.answers.len() + message.authorities.len() + message.additionals.len()
+}
+
+/// Reads the message in place and walks every owner name, as a forwarder matching records would
+fn parse_borrowed(bytes: &[u8]) -> usize {
+    let message = MessageRef::from_bytes(bytes).unwrap();
+    [
+        MsgSection::Answers,
+        MsgSection::Authorities,
+        MsgSection::Additionals,
+    ]
+    .into_iter()
+    .flat_map(|section| message.records(section))
+    .map(|record| record.name.labels().count())
+    .sum()
+}
+
+fn allocations(parse: fn(&[u8]) -> usize, bytes: &[u8]) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(parse(black_box(bytes)));
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let bytes = response();
+    println!(
+        "allocations per message: Message {}, MessageRef {}",
+        allocations(parse_owned, &bytes),
+        allocations(parse_borrowed, &bytes)
+    );
+
+    let mut group = c.benchmark_group("parse");
+    group.bench_function("Message", |b| b.iter(|| parse_owned(black_box(&bytes))));
+    group.bench_function("MessageRef", |b| {
+        b.iter(|| parse_borrowed(black_box(&bytes)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,706 @@
+//! An in-memory cache of resource record sets, so that repeated lookups need not query name servers again.
+//!
+//! Records are kept for as long as their TTL allows. Referrals are cached as well,
+//! letting later lookups start from the closest known zone cut instead of the root.
+//!
+//! Each RRset is ranked by how far the section of the response it came from may be trusted,
+//! as described in [RFC 2181 section 5.4.1](https://datatracker.ietf.org/doc/html/rfc2181#section-5.4.1).
+//! Glue and referrals guide resolution towards the servers of a zone, but are never given as answers,
+//! and never replace better ranked data.
+//!
+//! Negative answers (NXDOMAIN and NODATA) are cached for the lesser of the TTL and the MINIMUM field
+//! of the SOA record in the authority section, as described in
+//! [RFC 2308 section 5](https://datatracker.ietf.org/doc/html/rfc2308#section-5).
+
+use std::{
+    collections::{hash_map, BTreeMap, HashMap},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    dname::DomainName, message::Message, qclass::QClass, qtype::QType, rcode::Rcode, rdata::RData,
+    record::Record,
+};
+
+/// What the cache knows about a name, type and class
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cached {
+    /// The RRset, with TTLs counting down the time it has left in the cache
+    Records(Vec<Record>),
+    /// The name exists, but has no records of the type (NODATA)
+    NoData {
+        /// the SOA record of the zone that gave the negative answer
+        soa: Record,
+    },
+    /// The name does not exist (NXDOMAIN)
+    NxDomain {
+        /// the SOA record of the zone that gave the negative answer
+        soa: Record,
+    },
+}
+
+/// How far cached data may be trusted, least first, see
+/// [RFC 2181 section 5.4.1](https://datatracker.ietf.org/doc/html/rfc2181#section-5.4.1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Trust {
+    /// from the additional section, or the authority section of a response that is not authoritative,
+    /// e.g. a referral and its glue. Never given as an answer
+    Additional,
+    /// from the answer section of a response that is not authoritative
+    Answer,
+    /// from the authority section of an authoritative answer
+    AuthoritativeAuthority,
+    /// from the answer section of an authoritative answer
+    AuthoritativeAnswer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    name: DomainName,
+    qtype: QType,
+    class: QClass,
+}
+
+/// Where an entry is held, so that it can be found from the expiry index
+#[derive(Debug, Clone)]
+enum Slot {
+    Entry(Key),
+    NxDomain(DomainName, QClass),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    data: Cached,
+    expires: Instant,
+    trust: Trust,
+    /// tells apart entries expiring at the same instant in the expiry index
+    id: u64,
+}
+
+/// A cache of RRsets and negative answers keyed by name, type and class
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: HashMap<Key, Entry>,
+    /// NXDOMAIN answers hold for every type of a name
+    nxdomains: HashMap<(DomainName, QClass), Entry>,
+    /// every entry by when it expires, so that purging and evicting need not scan the whole cache
+    expiry: BTreeMap<(Instant, u64), Slot>,
+    /// the ID of the next entry stored
+    next_id: u64,
+    /// the most RRsets and negative answers held at once, unbounded if [`None`]
+    capacity: Option<usize>,
+}
+
+impl Cache {
+    /// Creates an empty, unbounded [`Cache`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty [`Cache`] holding at most `capacity` RRsets and negative answers.
+    ///
+    /// Once full, expired entries are dropped first, then those closest to expiring.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// The number of RRsets and negative answers held, including expired ones not yet purged
+    pub fn len(&self) -> usize {
+        self.entries.len() + self.nxdomains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes everything from the cache
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.nxdomains.clear();
+        self.expiry.clear();
+    }
+
+    /// Removes all entries whose TTL has run out
+    pub fn purge_expired(&mut self) {
+        self.purge_expired_at(Instant::now())
+    }
+
+    fn purge_expired_at(&mut self, now: Instant) {
+        while let Some(soonest) = self.expiry.first_entry() {
+            if soonest.key().0 > now {
+                break;
+            }
+            let slot = soonest.remove();
+            self.take(&slot);
+        }
+    }
+
+    /// Looks up what is known about `name`'s records of type `qtype`, trusted enough to answer a query
+    pub fn get(&self, name: &DomainName, qtype: QType, class: QClass) -> Option<Cached> {
+        self.get_at(name, qtype, class, Instant::now())
+    }
+
+    /// Like [`Cache::get`], but only considering data ranked at least `trust`, e.g. [`Trust::Additional`]
+    /// to include glue
+    pub fn get_trusted(
+        &self,
+        name: &DomainName,
+        qtype: QType,
+        class: QClass,
+        trust: Trust,
+    ) -> Option<Cached> {
+        self.get_trusted_at(name, qtype, class, trust, Instant::now())
+    }
+
+    fn get_at(
+        &self,
+        name: &DomainName,
+        qtype: QType,
+        class: QClass,
+        now: Instant,
+    ) -> Option<Cached> {
+        self.get_trusted_at(name, qtype, class, Trust::Answer, now)
+    }
+
+    fn get_trusted_at(
+        &self,
+        name: &DomainName,
+        qtype: QType,
+        class: QClass,
+        trust: Trust,
+        now: Instant,
+    ) -> Option<Cached> {
+        let key = Key {
+            name: name.clone(),
+            qtype,
+            class,
+        };
+
+        let entry = self
+            .nxdomains
+            .get(&(name.clone(), class))
+            .or_else(|| self.entries.get(&key))
+            .filter(|entry| entry.expires > now && entry.trust >= trust)?;
+
+        let remaining = entry.expires.duration_since(now).as_secs() as u32;
+        let mut data = entry.data.clone();
+        match &mut data {
+            Cached::Records(records) => {
+                for record in records {
+                    record.time_to_live = record.time_to_live.min(remaining);
+                }
+            }
+            Cached::NoData { soa } | Cached::NxDomain { soa } => {
+                soa.time_to_live = soa.time_to_live.min(remaining)
+            }
+        }
+        Some(data)
+    }
+
+    /// Stores an RRset, i.e. records sharing a name, type and class, ranked `trust`.
+    ///
+    /// The set is kept for the lowest TTL among its records; sets with a zero TTL are not stored,
+    /// nor are sets ranked below the set they would replace.
+    pub fn insert_rrset(&mut self, records: Vec<Record>, trust: Trust) {
+        self.insert_rrset_at(records, trust, Instant::now())
+    }
+
+    fn insert_rrset_at(&mut self, records: Vec<Record>, trust: Trust, now: Instant) {
+        let Some(first) = records.first() else {
+            return;
+        };
+        let Some(ttl) = records.iter().map(|rr| rr.time_to_live).min() else {
+            return;
+        };
+        if ttl == 0 {
+            return;
+        }
+
+        let key = Key {
+            name: first.name.clone(),
+            qtype: first.qtype,
+            class: first.class,
+        };
+        let outranked = self
+            .entries
+            .get(&key)
+            .is_some_and(|held| held.expires > now && held.trust > trust);
+        if outranked {
+            return;
+        }
+        // an answer for a name supersedes an earlier NXDOMAIN
+        if trust >= Trust::Answer {
+            self.remove(&Slot::NxDomain(key.name.clone(), key.class));
+        }
+        let expires = now + Duration::from_secs(ttl.into());
+        self.store(Slot::Entry(key), Cached::Records(records), expires, trust);
+    }
+
+    /// Stores that `name` exists but has no records of type `qtype`
+    pub fn insert_nodata(&mut self, name: DomainName, qtype: QType, class: QClass, soa: Record) {
+        self.insert_nodata_at(name, qtype, class, soa, Instant::now())
+    }
+
+    fn insert_nodata_at(
+        &mut self,
+        name: DomainName,
+        qtype: QType,
+        class: QClass,
+        soa: Record,
+        now: Instant,
+    ) {
+        if let Some(expires) = Self::negative_expiry(&soa, now) {
+            let key = Key { name, qtype, class };
+            // ranked lowest among answers, so that any answer for the name supersedes it
+            self.store(
+                Slot::Entry(key),
+                Cached::NoData { soa },
+                expires,
+                Trust::Answer,
+            );
+        }
+    }
+
+    /// Stores that `name` does not exist
+    pub fn insert_nxdomain(&mut self, name: DomainName, class: QClass, soa: Record) {
+        self.insert_nxdomain_at(name, class, soa, Instant::now())
+    }
+
+    fn insert_nxdomain_at(&mut self, name: DomainName, class: QClass, soa: Record, now: Instant) {
+        if let Some(expires) = Self::negative_expiry(&soa, now) {
+            self.store(
+                Slot::NxDomain(name, class),
+                Cached::NxDomain { soa },
+                expires,
+                Trust::Answer,
+            );
+        }
+    }
+
+    /// Stores an entry in `slot`, replacing whatever it held, and evicts others if the cache is full
+    fn store(&mut self, slot: Slot, data: Cached, expires: Instant, trust: Trust) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.expiry.insert((expires, id), slot.clone());
+
+        let entry = Entry {
+            data,
+            expires,
+            trust,
+            id,
+        };
+        let replaced = match slot {
+            Slot::Entry(key) => self.entries.insert(key, entry),
+            Slot::NxDomain(name, class) => self.nxdomains.insert((name, class), entry),
+        };
+        if let Some(replaced) = replaced {
+            self.expiry.remove(&(replaced.expires, replaced.id));
+        }
+        self.evict();
+    }
+
+    /// Removes the entry in `slot`, if any
+    fn remove(&mut self, slot: &Slot) {
+        if let Some(removed) = self.take(slot) {
+            self.expiry.remove(&(removed.expires, removed.id));
+        }
+    }
+
+    /// Removes the entry in `slot` without touching the expiry index
+    fn take(&mut self, slot: &Slot) -> Option<Entry> {
+        match slot {
+            Slot::Entry(key) => self.entries.remove(key),
+            Slot::NxDomain(name, class) => self.nxdomains.remove(&(name.clone(), *class)),
+        }
+    }
+
+    /// Drops the entries closest to expiring, expired ones first, until the cache is within its capacity
+    fn evict(&mut self) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        while self.len() > capacity {
+            let Some((_, slot)) = self.expiry.pop_first() else {
+                break;
+            };
+            self.take(&slot);
+        }
+    }
+
+    /// Negative answers are kept for the lesser of the SOA record's TTL and its MINIMUM field
+    fn negative_expiry(soa: &Record, now: Instant) -> Option<Instant> {
+        let RData::SOA { minimum, .. } = soa.rdata else {
+            return None;
+        };
+        let ttl = soa.time_to_live.min(minimum);
+        (ttl > 0).then(|| now + Duration::from_secs(ttl.into()))
+    }
+
+    /// Stores every RRset in a response, ranked by the section it is in,
+    /// along with any negative answer it gives to its question
+    pub fn insert_message(&mut self, msg: &Message) {
+        let (answer, authority) = if msg.header.flags.is_authoritative() {
+            (Trust::AuthoritativeAnswer, Trust::AuthoritativeAuthority)
+        } else {
+            (Trust::Answer, Trust::Additional)
+        };
+        let sections = [
+            (&msg.answers, answer),
+            (&msg.authorities, authority),
+            (&msg.additionals, Trust::Additional),
+        ];
+
+        let mut rrsets: HashMap<Key, (Vec<Record>, Trust)> = HashMap::new();
+        for (section, trust) in sections {
+            let mut section_rrsets: HashMap<Key, Vec<Record>> = HashMap::new();
+            for record in section {
+                let key = Key {
+                    name: record.name.clone(),
+                    qtype: record.qtype,
+                    class: record.class,
+                };
+                let records = section_rrsets.entry(key).or_default();
+                if !records.contains(record) {
+                    records.push(record.clone());
+                }
+            }
+            // an RRset repeated in several sections is taken from the best ranked of them alone,
+            // so that records from a lesser section cannot borrow its rank
+            for (key, records) in section_rrsets {
+                match rrsets.entry(key) {
+                    hash_map::Entry::Occupied(mut held) => {
+                        if held.get().1 < trust {
+                            held.insert((records, trust));
+                        }
+                    }
+                    hash_map::Entry::Vacant(vacant) => {
+                        vacant.insert((records, trust));
+                    }
+                }
+            }
+        }
+        for (_, (records, trust)) in rrsets {
+            self.insert_rrset(records, trust);
+        }
+
+        let Some(question) = msg.questions.first() else {
+            return;
+        };
+        let Some(soa) = msg
+            .authorities
+            .iter()
+            .find(|rr| rr.qtype == QType::SOA)
+            .cloned()
+        else {
+            return;
+        };
+
+        // the negative answer is about the end of any CNAME chain in the answers
+        let mut name = question.qname.clone();
+        while let Some(target) = msg
+            .answers
+            .iter()
+            .find(|rr| rr.name == name && rr.qtype == QType::CNAME)
+            .and_then(|rr| rr.rdata.as_name())
+        {
+            name = target.clone();
+        }
+
+        let answered = msg
+            .answers
+            .iter()
+            .any(|rr| rr.name == name && rr.qtype == question.qtype);
+
+        match msg.rcode() {
+            Rcode::NXDOMAIN => self.insert_nxdomain(name, question.qclass, soa),
+            Rcode::NOERROR if !answered => {
+                self.insert_nodata(name, question.qtype, question.qclass, soa)
+            }
+            _ => {}
+        }
+    }
+
+    /// Finds the cached name servers closest to `name`, along with the zone they serve.
+    ///
+    /// Only name servers with a cached address are considered. Referrals and glue count too.
+    pub fn nameservers(
+        &self,
+        name: &DomainName,
+        class: QClass,
+    ) -> Option<(DomainName, Vec<IpAddr>)> {
+        let mut zone = Some(name.clone());
+        while let Some(current) = zone {
+            let ns_records = self.get_trusted(&current, QType::NS, class, Trust::Additional);
+            if let Some(Cached::Records(ns_records)) = ns_records {
+                let addrs: Vec<IpAddr> = ns_records
+                    .iter()
+                    .filter_map(|rr| rr.rdata.as_name())
+                    .flat_map(|ns| {
+                        [QType::A, QType::AAAA]
+                            .map(|qtype| self.get_trusted(ns, qtype, class, Trust::Additional))
+                    })
+                    .flat_map(|cached| match cached {
+                        Some(Cached::Records(records)) => records,
+                        _ => Vec::new(),
+                    })
+                    .filter_map(|rr| rr.rdata.as_ip_addr())
+                    .collect();
+
+                if !addrs.is_empty() {
+                    return Some((current, addrs));
+                }
+            }
+            zone = current.parent();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::test_util::record;
+
+    fn soa(time_to_live: u32, minimum: u32) -> Record {
+        record(
+            "example.com",
+            time_to_live,
+            RData::SOA {
+                mname: DomainName::new("ns.example.com"),
+                rname: DomainName::new("admin.example.com"),
+                serial: 1,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum,
+            },
+        )
+    }
+
+    #[test]
+    fn records_expire_with_ttl() {
+        let mut cache = Cache::new();
+        let now = Instant::now();
+        let name = DomainName::new("www.example.com");
+        let a = |ttl| {
+            record(
+                "www.example.com",
+                ttl,
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            )
+        };
+
+        cache.insert_rrset_at(vec![a(300)], Trust::AuthoritativeAnswer, now);
+
+        let later = now + Duration::from_secs(100);
+        assert_eq!(
+            cache.get_at(&name, QType::A, QClass::IN, later),
+            Some(Cached::Records(vec![a(200)]))
+        );
+        assert_eq!(cache.get_at(&name, QType::AAAA, QClass::IN, later), None);
+
+        let expired = now + Duration::from_secs(300);
+        assert_eq!(cache.get_at(&name, QType::A, QClass::IN, expired), None);
+    }
+
+    #[test]
+    fn zero_ttl_not_cached() {
+        let mut cache = Cache::new();
+        let a = record("www.example.com", 0, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+
+        cache.insert_rrset(vec![a], Trust::AuthoritativeAnswer);
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn negative_ttl_from_soa_minimum() {
+        let mut cache = Cache::new();
+        let now = Instant::now();
+        let missing = DomainName::new("missing.example.com");
+        let bare = DomainName::new("example.com");
+
+        cache.insert_nxdomain_at(missing.clone(), QClass::IN, soa(3600, 60), now);
+        cache.insert_nodata_at(bare.clone(), QType::MX, QClass::IN, soa(30, 900), now);
+
+        let later = now + Duration::from_secs(20);
+        // an NXDOMAIN holds for all types
+        assert_eq!(
+            cache.get_at(&missing, QType::TXT, QClass::IN, later),
+            Some(Cached::NxDomain { soa: soa(40, 60) })
+        );
+        assert_eq!(
+            cache.get_at(&bare, QType::MX, QClass::IN, later),
+            Some(Cached::NoData { soa: soa(10, 900) })
+        );
+        assert_eq!(cache.get_at(&bare, QType::A, QClass::IN, later), None);
+
+        let much_later = now + Duration::from_secs(45);
+        assert_eq!(cache.get_at(&bare, QType::MX, QClass::IN, much_later), None);
+        assert!(cache
+            .get_at(&missing, QType::A, QClass::IN, much_later)
+            .is_some());
+    }
+
+    #[test]
+    fn closest_nameservers() {
+        let mut cache = Cache::new();
+        let ns = |zone, host: &str| record(zone, 3600, RData::NS(DomainName::new(host)));
+        let glue = |host, last| record(host, 3600, RData::A(Ipv4Addr::new(192, 0, 2, last)));
+
+        cache.insert_rrset(vec![ns("com", "a.gtld-servers.net")], Trust::Additional);
+        cache.insert_rrset(vec![glue("a.gtld-servers.net", 30)], Trust::Additional);
+        // no address known for this zone's servers
+        cache.insert_rrset(vec![ns("example.com", "ns.example.net")], Trust::Additional);
+
+        let (zone, addrs) = cache
+            .nameservers(&DomainName::new("www.example.com"), QClass::IN)
+            .unwrap();
+        assert_eq!(zone, DomainName::new("com"));
+        assert_eq!(addrs, vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 30))]);
+
+        assert_eq!(
+            cache.nameservers(&DomainName::new("example.org"), QClass::IN),
+            None
+        );
+    }
+
+    #[test]
+    fn glue_never_answers() {
+        let mut cache = Cache::new();
+        let name = DomainName::new("ns.example.com");
+        let a = |last| {
+            record(
+                "ns.example.com",
+                3600,
+                RData::A(Ipv4Addr::new(192, 0, 2, last)),
+            )
+        };
+        let referral = Message {
+            header: crate::header::Header {
+                id: 1,
+                flags: crate::header::Flags::new().with_response(true),
+                num_questions: 0,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 0,
+            },
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: vec![record(
+                "example.com",
+                3600,
+                RData::NS(DomainName::new("ns.example.com")),
+            )],
+            additionals: vec![a(1)],
+            edns: None,
+        };
+
+        // TTLs count down in the meantime, so only the data is compared
+        let held = |cached: Option<Cached>| match cached {
+            Some(Cached::Records(records)) => records.into_iter().map(|rr| rr.rdata).collect(),
+            _ => Vec::new(),
+        };
+
+        cache.insert_message(&referral);
+        assert_eq!(cache.get(&name, QType::A, QClass::IN), None);
+        assert_eq!(
+            held(cache.get_trusted(&name, QType::A, QClass::IN, Trust::Additional)),
+            vec![a(1).rdata]
+        );
+        assert!(cache
+            .nameservers(&DomainName::new("www.example.com"), QClass::IN)
+            .is_some());
+
+        // an authoritative answer replaces the glue, which cannot replace it in turn
+        cache.insert_rrset(vec![a(2)], Trust::AuthoritativeAnswer);
+        cache.insert_message(&referral);
+        assert_eq!(
+            held(cache.get(&name, QType::A, QClass::IN)),
+            vec![a(2).rdata]
+        );
+    }
+
+    #[test]
+    fn sections_ranked_apart() {
+        let mut cache = Cache::new();
+        let name = DomainName::new("www.example.com");
+        let a = |last| {
+            record(
+                "www.example.com",
+                3600,
+                RData::A(Ipv4Addr::new(192, 0, 2, last)),
+            )
+        };
+        // the same RRset in the additional section, with a record the answer does not hold
+        let resp = Message {
+            header: crate::header::Header {
+                id: 1,
+                flags: crate::header::Flags::new()
+                    .with_response(true)
+                    .with_authoritative(true),
+                num_questions: 0,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 0,
+            },
+            questions: Vec::new(),
+            answers: vec![a(1)],
+            authorities: Vec::new(),
+            additionals: vec![a(1), a(66)],
+            edns: None,
+        };
+
+        cache.insert_message(&resp);
+        let held = match cache.get(&name, QType::A, QClass::IN) {
+            Some(Cached::Records(records)) => records.into_iter().map(|rr| rr.rdata).collect(),
+            _ => Vec::new(),
+        };
+        assert_eq!(held, vec![a(1).rdata]);
+    }
+
+    #[test]
+    fn evicts_soonest_to_expire_when_full() {
+        let mut cache = Cache::with_capacity(2);
+        let now = Instant::now();
+        let a = |name, ttl| record(name, ttl, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+
+        cache.insert_rrset_at(
+            vec![a("long.example.com", 3600)],
+            Trust::AuthoritativeAnswer,
+            now,
+        );
+        cache.insert_rrset_at(
+            vec![a("short.example.com", 60)],
+            Trust::AuthoritativeAnswer,
+            now,
+        );
+        cache.insert_rrset_at(
+            vec![a("medium.example.com", 600)],
+            Trust::AuthoritativeAnswer,
+            now,
+        );
+
+        assert_eq!(cache.len(), 2);
+        let held = |name| cache.get_at(&DomainName::new(name), QType::A, QClass::IN, now);
+        assert!(held("long.example.com").is_some());
+        assert!(held("medium.example.com").is_some());
+        assert_eq!(held("short.example.com"), None);
+
+        // a replaced set expires when its replacement does
+        cache.insert_rrset_at(
+            vec![a("medium.example.com", 7200)],
+            Trust::AuthoritativeAnswer,
+            now,
+        );
+        cache.purge_expired_at(now + Duration::from_secs(3600));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.expiry.len(), 1);
+    }
+}
@@ -4,17 +4,108 @@
 //! and [RFC 1035 section 3.1](https://datatracker.ietf.org/doc/html/rfc1035#section-3.1)
 
 mod label {
-    use std::io::{Cursor, Read};
+    use std::hash::{Hash, Hasher};
 
+    use rand::Rng;
     use thiserror::Error;
 
-    /// Labels are the individual nodes or components of a [`DomainName`]
-    #[derive(Debug, Clone, PartialEq, Eq)]
-    pub struct Label(String);
+    /// Labels are the individual nodes or components of a [`DomainName`](super::DomainName)
+    ///
+    /// A label is any sequence of octets, which need not be text.
+    /// Labels compare and hash without regard to ASCII case, see
+    /// [RFC 4343 section 3](https://datatracker.ietf.org/doc/html/rfc4343#section-3)
+    #[derive(Debug, Clone)]
+    pub struct Label(Vec<u8>);
 
     impl Label {
-        pub fn new(string: String) -> Self {
-            Self(string)
+        /// The prefix of labels holding the ASCII-compatible encoding of an internationalized label
+        pub const ACE_PREFIX: &'static str = "xn--";
+
+        /// Creates a [`Label`], which must hold between 1 and [`Label::MAX_LABEL_SIZE`] octets
+        pub fn new(octets: Vec<u8>) -> Result<Self> {
+            match octets.len() {
+                0 => Err(Error::Empty),
+                len if len > Self::MAX_LABEL_SIZE => Err(Error::TooLong { len }),
+                _ => Ok(Self(octets)),
+            }
+        }
+
+        pub fn as_bytes(&self) -> &[u8] {
+            &self.0
+        }
+
+        /// Whether both labels hold exactly the same octets, including their case
+        pub fn eq_exact(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+
+        /// Returns a copy of the label with each ASCII letter's case chosen at random
+        pub fn randomize_case(&self, rng: &mut impl Rng) -> Self {
+            let randomized = self
+                .0
+                .iter()
+                .map(|byte| {
+                    if rng.gen() {
+                        byte.to_ascii_uppercase()
+                    } else {
+                        byte.to_ascii_lowercase()
+                    }
+                })
+                .collect();
+            Self(randomized)
+        }
+
+        /// The Unicode form (U-label) of an internationalized label held as an A-label, e.g.
+        /// "münchen" for "xn--mnchen-3ya", or [`None`] if the label is not a valid A-label, see
+        /// [RFC 5890 section 2.3.2.1](https://datatracker.ietf.org/doc/html/rfc5890#section-2.3.2.1)
+        pub fn to_unicode(&self) -> Option<String> {
+            let text = std::str::from_utf8(&self.0).ok()?;
+            if !text.get(..4)?.eq_ignore_ascii_case(Self::ACE_PREFIX) {
+                return None;
+            }
+            let (unicode, result) = idna::domain_to_unicode(text);
+            result.ok()?;
+            // the decoded label must not turn into several
+            (!unicode.contains('.')).then_some(unicode)
+        }
+    }
+
+    impl PartialEq for Label {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.eq_ignore_ascii_case(&other.0)
+        }
+    }
+
+    impl Eq for Label {}
+
+    /// Labels are ordered as unsigned left-justified octet strings, with uppercase ASCII letters
+    /// treated as if they were lowercase, see
+    /// [RFC 4034 section 6.1](https://datatracker.ietf.org/doc/html/rfc4034#section-6.1)
+    impl Ord for Label {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            let lowercase = |label: &Self| {
+                label
+                    .0
+                    .iter()
+                    .map(u8::to_ascii_lowercase)
+                    .collect::<Vec<u8>>()
+            };
+            lowercase(self).cmp(&lowercase(other))
+        }
+    }
+
+    impl PartialOrd for Label {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Hash for Label {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            state.write_usize(self.0.len());
+            for byte in &self.0 {
+                state.write_u8(byte.to_ascii_lowercase());
+            }
         }
     }
 
@@ -24,34 +115,47 @@ mod label {
 
         pub fn into_bytes(self) -> Vec<u8> {
             let size = self.0.len();
-            let mut buf = self.0.into_bytes();
+            let mut buf = self.0;
             buf.splice(0..0, [size as u8]);
             buf
         }
 
-        pub fn read_label(bytes: &mut Cursor<&[u8]>, dest: &mut [u8]) -> Result<Self> {
-            bytes.read_exact(dest).map_err(|source| {
-                let mut owned_bytes = Cursor::new(bytes.get_ref().to_vec());
-                owned_bytes.set_position(bytes.position());
-                Error::Io {
-                    src_bytes: owned_bytes,
-                    dest_amt: dest.len(),
-                    source,
+        /// Appends the length octet and the label octets to `buf`
+        pub fn write_to(&self, buf: &mut Vec<u8>) {
+            buf.push(self.0.len() as u8);
+            buf.extend_from_slice(&self.0);
+        }
+
+        /// Copies the octets of a label read from a message, whose size the caller already checked
+        pub(super) fn from_wire(octets: &[u8]) -> Self {
+            debug_assert!((1..=Self::MAX_LABEL_SIZE).contains(&octets.len()));
+            Self(octets.to_vec())
+        }
+
+        /// Writes `octets` in presentation format, see [`Label`]'s [`Display`](std::fmt::Display)
+        pub(super) fn fmt_octets(
+            octets: &[u8],
+            f: &mut std::fmt::Formatter<'_>,
+        ) -> std::fmt::Result {
+            for &byte in octets {
+                match byte {
+                    b'.' | b'\\' | b'"' | b'(' | b')' | b';' | b'@' | b'$' => {
+                        write!(f, "\\{}", byte as char)?
+                    }
+                    0x21..=0x7e => write!(f, "{}", byte as char)?,
+                    _ => write!(f, "\\{byte:03}")?,
                 }
-            })?;
-            let label = std::str::from_utf8(dest)
-                .map_err(|source| Error::Convert {
-                    bytes: dest.to_vec(),
-                    source,
-                })?
-                .to_string();
-            Ok(Self(label))
+            }
+            Ok(())
         }
     }
 
-    impl ToString for Label {
-        fn to_string(&self) -> String {
-            self.0.clone()
+    impl std::fmt::Display for Label {
+        /// Writes the label in presentation format, escaping octets that are special in master files
+        /// with a backslash, and those that are not printable ASCII as `\DDD` (decimal), see
+        /// [RFC 1035 section 5.1](https://datatracker.ietf.org/doc/html/rfc1035#section-5.1)
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            Self::fmt_octets(&self.0, f)
         }
     }
 
@@ -59,32 +163,38 @@ mod label {
     #[derive(Debug, Error)]
     pub enum Error {
         /// Stores an error encountered while using [std::io] traits and structs
-        #[error("Failed to read {dest_amt} bytes from {src_bytes:?}:\n\t{source}")]
+        #[error("Failed to read {dest_amt} bytes at {position}:\n\t{source}")]
         Io {
-            src_bytes: Cursor<Vec<u8>>,
+            position: u64,
             dest_amt: usize,
             source: std::io::Error,
         },
-        /// Stores an error encountered while converting from a sequence of [u8] to [String]
-        #[error("Failed to convert byte slice {bytes:?} to string slice:\n\t{source}")]
-        Convert {
-            bytes: Vec<u8>,
-            source: std::str::Utf8Error,
-        },
+        /// Only the root may have an empty label, which is never written out as text
+        #[error("Labels may not be empty")]
+        Empty,
+        /// Labels hold at most [`Label::MAX_LABEL_SIZE`] octets
+        #[error(
+            "Label of {len} octets exceeds the maximum of {}",
+            Label::MAX_LABEL_SIZE
+        )]
+        TooLong { len: usize },
     }
 
     type Result<T> = std::result::Result<T, Error>;
 }
 
-use label::Label;
+pub use label::Label;
 
-use std::io::{Cursor, Seek, SeekFrom};
+use std::{collections::HashMap, io::Cursor};
 
 use byteorder::ReadBytesExt;
 use thiserror::Error;
 
 /// Domain names define a name of a node in requests and responses
-#[derive(Clone, PartialEq, Eq)]
+///
+/// Names compare and hash without regard to ASCII case, and are ordered canonically,
+/// as described in [RFC 4034 section 6.1](https://datatracker.ietf.org/doc/html/rfc4034#section-6.1).
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct DomainName(Vec<Label>);
 
 impl DomainName {
@@ -93,6 +203,28 @@ impl DomainName {
     pub const MAX_UDP_MSG_SIZE: usize = 512;
     /// a domain name is terminated by a length byte of zero
     pub const TERMINATOR: u8 = 0;
+    /// The two high bits set on a length octet mark the start of a compression pointer
+    pub const POINTER_MASK: u8 = 0b1100_0000;
+    /// The two high bits of a length octet select the label type, of which only
+    /// `00` (a plain label) and `11` (a compression pointer) are defined, see
+    /// [RFC 6891 section 5](https://datatracker.ietf.org/doc/html/rfc6891#section-5)
+    pub const LABEL_TYPE_MASK: u8 = 0b1100_0000;
+    /// The largest message offset a compression pointer can hold (14 bits)
+    pub const MAX_POINTER_OFFSET: usize = 0x3FFF;
+    /// The most compression pointers followed while reading a single name.
+    ///
+    /// A name holds at most 127 labels, each of which may be reached through one pointer.
+    pub const MAX_POINTER_HOPS: usize = 127;
+}
+
+/// Remembers where names were written in a message, so that later names
+/// sharing a suffix with them can be replaced by a pointer.
+///
+/// See more in [RFC 1035 section 4.1.4](https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.4)
+#[derive(Debug, Default)]
+pub struct Compressor {
+    /// message offsets of every name suffix written so far
+    offsets: HashMap<Vec<Label>, u16>,
 }
 
 impl std::fmt::Debug for DomainName {
@@ -103,25 +235,47 @@ impl std::fmt::Debug for DomainName {
     }
 }
 
-impl From<String> for DomainName {
-    fn from(value: String) -> Self {
-        Self(
-            value
-                .split('.')
-                .map(|substr| Label::new(substr.to_string()))
-                .collect(),
-        )
+impl std::fmt::Display for DomainName {
+    /// Writes the labels separated by dots, without a trailing dot, or a single dot for the root
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+        for (idx, label) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{label}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Canonical order sorts names by their labels, starting from the rightmost (most significant),
+/// so that a name sorts directly before its subdomains.
+impl Ord for DomainName {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for DomainName {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl std::str::FromStr for DomainName {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
     }
 }
 
 impl From<DomainName> for String {
     fn from(value: DomainName) -> Self {
-        value
-            .0
-            .into_iter()
-            .map(|label| label.to_string())
-            .reduce(|acc, label_str| acc + "." + &label_str)
-            .unwrap_or_default()
+        value.to_string()
     }
 }
 
@@ -134,60 +288,396 @@ impl DomainName {
         val
     }
 
+    /// Writes a [`DomainName`] to the end of `buf`, which must hold the message from its first octet.
+    ///
+    /// The longest suffix already seen by `compressor` is replaced by a pointer to it.
+    pub fn write_compressed(&self, buf: &mut Vec<u8>, compressor: &mut Compressor) {
+        for (idx, label) in self.0.iter().enumerate() {
+            let suffix = &self.0[idx..];
+            if let Some(&offset) = compressor.offsets.get(suffix) {
+                let [high, low] = offset.to_be_bytes();
+                buf.extend_from_slice(&[high | DomainName::POINTER_MASK, low]);
+                return;
+            }
+            if buf.len() <= DomainName::MAX_POINTER_OFFSET {
+                compressor.offsets.insert(suffix.to_vec(), buf.len() as u16);
+            }
+            label.write_to(buf);
+        }
+        buf.push(DomainName::TERMINATOR);
+    }
+
     pub const fn is_compressed(size: u8) -> bool {
-        size & 0b1100_0000 == 0b1100_0000
+        size & DomainName::POINTER_MASK == DomainName::POINTER_MASK
     }
 
-    fn read_compressed_label(bytes: &mut Cursor<&[u8]>, size: u8) -> Result<Vec<Label>> {
-        // get pointed-to name
-        let second = bytes.read_u8()?;
-        let name_pos = u16::from_be_bytes([size & 0b0011_1111, second]);
+    /// Reads a [`DomainName`] from a slice of bytes holding the message from its first octet,
+    /// following compression pointers.
+    ///
+    /// Each pointer must point strictly before the labels read since the name's start or the last
+    /// pointer followed, so that pointers cannot form a loop. At most
+    /// [`DomainName::MAX_POINTER_HOPS`] pointers are followed.
+    /// The cursor is left after the name as it appears in place, i.e. after its first pointer.
+    pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
+        let (name, end) = NameRef::read(bytes.get_ref(), bytes.position() as usize)?;
+        bytes.set_position(end as u64);
+        Ok(name.to_name())
+    }
 
-        // save current pos
-        let old_pos = bytes.position();
+    /// Parses a [`DomainName`] from its presentation format: labels separated by dots.
+    ///
+    /// A trailing dot (the fully qualified form) is optional, and both "" and "." are the root.
+    /// Within a label, `\X` stands for the character X (e.g. `\.` for a dot that does not end the label)
+    /// and `\DDD` for the octet with decimal value DDD, see
+    /// [RFC 1035 section 5.1](https://datatracker.ietf.org/doc/html/rfc1035#section-5.1).
+    ///
+    /// Names holding non-ASCII characters are internationalized: they are mapped and converted
+    /// to A-labels (e.g. "xn--mnchen-3ya" for "münchen") as described in
+    /// [UTS #46](https://www.unicode.org/reports/tr46/) and
+    /// [RFC 5891](https://datatracker.ietf.org/doc/html/rfc5891), see [`DomainName::to_unicode`] for the reverse.
+    pub fn parse(domain_name: &str) -> Result<Self> {
+        if domain_name.is_empty() || domain_name == "." {
+            return Ok(Self::root());
+        }
+        if !domain_name.is_ascii() {
+            let ascii = idna::domain_to_ascii(domain_name).map_err(|source| Error::Idna {
+                name: domain_name.to_string(),
+                source,
+            })?;
+            return Self::parse(&ascii);
+        }
 
-        // get name
-        bytes.seek(SeekFrom::Start(name_pos as u64))?;
-        let name = DomainName::from_bytes(bytes)?;
+        let mut labels = Vec::new();
+        let mut octets = Vec::new();
+        let mut label_start = 0;
+        // a trailing unescaped dot does not start another label
+        let mut ended_with_dot = false;
+
+        let mut chars = domain_name.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            ended_with_dot = false;
+            match c {
+                '.' => {
+                    let label = Label::new(std::mem::take(&mut octets)).map_err(|source| {
+                        Error::InvalidLabel {
+                            label: domain_name[label_start..idx].to_string(),
+                            source,
+                        }
+                    })?;
+                    labels.push(label);
+                    label_start = idx + 1;
+                    ended_with_dot = true;
+                }
+                '\\' => {
+                    let invalid_escape = || Error::InvalidEscape {
+                        name: domain_name.to_string(),
+                        position: idx,
+                    };
+                    match chars.next() {
+                        Some((_, first)) if first.is_ascii_digit() => {
+                            let mut value = first.to_digit(10).unwrap_or_default();
+                            for _ in 0..2 {
+                                let digit = chars
+                                    .next()
+                                    .and_then(|(_, c)| c.to_digit(10))
+                                    .ok_or_else(invalid_escape)?;
+                                value = value * 10 + digit;
+                            }
+                            octets.push(u8::try_from(value).map_err(|_| invalid_escape())?);
+                        }
+                        Some((_, escaped)) => {
+                            octets.extend_from_slice(escaped.encode_utf8(&mut [0; 4]).as_bytes())
+                        }
+                        None => return Err(invalid_escape()),
+                    }
+                }
+                c => octets.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        if !ended_with_dot {
+            let label = Label::new(octets).map_err(|source| Error::InvalidLabel {
+                label: domain_name[label_start..].to_string(),
+                source,
+            })?;
+            labels.push(label);
+        }
 
-        // reset to current pos
-        bytes.seek(SeekFrom::Start(old_pos))?;
-        Ok(name.0)
+        let name = Self(labels);
+        name.check_size()?;
+        Ok(name)
     }
 
-    /// Reads a [`DomainName`] from a slice of bytes
-    pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
-        // buffers and metadata storage
+    /// Creates a new [`DomainName`] from its labels separated by dots, meant for names known
+    /// to be valid, such as literals. Names from elsewhere, e.g. user input, go through
+    /// [`DomainName::parse`] instead.
+    ///
+    /// # Panics
+    ///
+    /// If `domain_name` is not a valid name.
+    pub fn new(domain_name: &str) -> Self {
+        Self::parse(domain_name)
+            .unwrap_or_else(|err| panic!("invalid domain name {domain_name:?}: {err}"))
+    }
 
-        let mut label_bytes_buffer = [0u8; Label::MAX_LABEL_SIZE];
-        let mut labels = Vec::new();
+    /// Writes the name like its [`Display`](std::fmt::Display) form, but with every A-label
+    /// converted to its Unicode form, e.g. "münchen.de" for "xn--mnchen-3ya.de".
+    ///
+    /// The result is meant for display only, since it does not escape the converted labels.
+    pub fn to_unicode(&self) -> String {
+        if self.is_root() {
+            return ".".to_string();
+        }
+        self.0
+            .iter()
+            .map(|label| label.to_unicode().unwrap_or_else(|| label.to_string()))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// The number of octets the uncompressed name takes up in a message
+    pub fn wire_size(&self) -> usize {
+        self.0
+            .iter()
+            .map(|label| label.as_bytes().len() + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    fn check_size(&self) -> Result<()> {
+        match self.wire_size() {
+            size if size > DomainName::MAX_NAME_SIZE => Err(Error::NameTooLong { size }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The root of the domain name space, holding no labels
+    pub fn root() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of labels in the name, not counting the root's empty label
+    pub fn num_labels(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The name's labels, starting from the leftmost (least significant)
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &Label> + ExactSizeIterator {
+        self.0.iter()
+    }
+
+    /// Returns the name with its leftmost label removed, or [`None`] for the root
+    pub fn parent(&self) -> Option<Self> {
+        self.0.split_first().map(|(_, rest)| Self(rest.to_vec()))
+    }
+
+    /// Whether both names hold exactly the same labels, including the case of their letters.
+    ///
+    /// Names otherwise compare without regard to ASCII case.
+    pub fn eq_exact(&self, other: &DomainName) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(&other.0)
+                .all(|(label, other)| label.eq_exact(other))
+    }
+
+    /// Returns a copy of the name with each ASCII letter's case chosen at random.
+    ///
+    /// Used to add entropy to queries, as servers echo the question's name as sent, see
+    /// [draft-vixie-dnsext-dns0x20](https://datatracker.ietf.org/doc/html/draft-vixie-dnsext-dns0x20-00)
+    pub fn randomize_case(&self) -> Self {
+        let mut rng = rand::thread_rng();
+        Self(
+            self.0
+                .iter()
+                .map(|label| label.randomize_case(&mut rng))
+                .collect(),
+        )
+    }
+
+    /// Whether this name is `zone` or lies beneath it, e.g. "www.example.com" is a subdomain of "com"
+    pub fn is_subdomain_of(&self, zone: &DomainName) -> bool {
+        self.0.ends_with(&zone.0)
+    }
+
+    /// Returns the name with `suffix` at its end replaced by `target`, as a DNAME record owned by `suffix`
+    /// redirects the names beneath it, see
+    /// [RFC 6672 section 2.2](https://datatracker.ietf.org/doc/html/rfc6672#section-2.2).
+    ///
+    /// Returns [`None`] if the name does not lie strictly beneath `suffix`, or if the result would be too long.
+    pub fn replace_suffix(&self, suffix: &DomainName, target: &DomainName) -> Option<Self> {
+        if self.0.len() <= suffix.0.len() || !self.is_subdomain_of(suffix) {
+            return None;
+        }
+        let prefix = &self.0[..self.0.len() - suffix.0.len()];
+        let name = Self(prefix.iter().chain(&target.0).cloned().collect());
+        name.check_size().ok()?;
+        Some(name)
+    }
+}
+
+/// A domain name read in place from a message, whose labels are only copied when asked to
+///
+/// Its compression pointers were checked when it was read, see [`NameRef::read`].
+#[derive(Clone, Copy)]
+pub struct NameRef<'a> {
+    message: &'a [u8],
+    start: usize,
+}
+
+impl<'a> NameRef<'a> {
+    /// Reads the name at `position` of `message`, which holds the message from its first octet.
+    ///
+    /// Compression pointers are followed under the same rules as [`DomainName::from_bytes`],
+    /// without copying any label. Returns the name and the position after it as it appears in place.
+    pub fn read(message: &'a [u8], position: usize) -> Result<(Self, usize)> {
+        let mut bytes = Cursor::new(message);
+        bytes.set_position(position as u64);
+
+        // the size of the name so far, counting the terminator
+        let mut size_so_far = 1;
+        // where the labels currently being read start
+        let mut segment_start = bytes.position();
+        // where the name ends in place, once a pointer has been followed
+        let mut end = None;
+        let mut hops = 0;
 
         loop {
             let size = bytes.read_u8()?;
 
             match size {
-                size if Self::is_compressed(size) => {
-                    labels.extend(Self::read_compressed_label(bytes, size)?);
-                    break;
+                size if DomainName::is_compressed(size) => {
+                    let second = bytes.read_u8()?;
+                    let offset = u16::from_be_bytes([size & !DomainName::POINTER_MASK, second]);
+                    let position = bytes.position() - 2;
+                    if u64::from(offset) >= segment_start {
+                        return Err(Error::ForwardPointer { position, offset });
+                    }
+                    hops += 1;
+                    if hops > DomainName::MAX_POINTER_HOPS {
+                        return Err(Error::TooManyPointers { position });
+                    }
+
+                    end.get_or_insert(bytes.position());
+                    segment_start = offset.into();
+                    bytes.set_position(segment_start);
                 }
-                DomainName::TERMINATOR => {
-                    break;
+                DomainName::TERMINATOR => break,
+                size if size & DomainName::LABEL_TYPE_MASK != 0 => {
+                    return Err(Error::ReservedLabelType { size });
                 }
                 _ => {
-                    let dest = &mut label_bytes_buffer[..size as usize];
-                    let label = Label::read_label(bytes, dest)
-                        .map_err(|source| Error::Label { size, source })?;
-                    labels.push(label);
+                    size_so_far += size as usize + 1;
+                    if size_so_far > DomainName::MAX_NAME_SIZE {
+                        return Err(Error::NameTooLong { size: size_so_far });
+                    }
+
+                    let label_start = bytes.position();
+                    let label_end = label_start + u64::from(size);
+                    if label_end > message.len() as u64 {
+                        let source = label::Error::Io {
+                            position: label_start,
+                            dest_amt: size.into(),
+                            source: std::io::ErrorKind::UnexpectedEof.into(),
+                        };
+                        return Err(Error::Label { size, source });
+                    }
+                    bytes.set_position(label_end);
                 }
             }
         }
 
-        Ok(Self(labels))
+        let name = Self {
+            message,
+            start: position,
+        };
+        Ok((name, end.unwrap_or(bytes.position()) as usize))
     }
 
-    /// Creates a new [`DomainName`]
-    pub fn new(domain_name: &str) -> Self {
-        DomainName::from(domain_name.to_string())
+    /// The name's labels, starting from the leftmost (least significant)
+    pub fn labels(&self) -> NameRefLabels<'a> {
+        NameRefLabels {
+            message: self.message,
+            position: self.start,
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// Copies the labels into an owned [`DomainName`]
+    pub fn to_name(&self) -> DomainName {
+        DomainName(self.labels().map(Label::from_wire).collect())
+    }
+}
+
+/// Compares the names without regard to ASCII case, like [`DomainName`]s
+impl PartialEq<DomainName> for NameRef<'_> {
+    fn eq(&self, other: &DomainName) -> bool {
+        let mut labels = self.labels();
+        other.labels().all(|label| {
+            labels
+                .next()
+                .is_some_and(|octets| label.as_bytes().eq_ignore_ascii_case(octets))
+        }) && labels.next().is_none()
+    }
+}
+
+impl std::fmt::Debug for NameRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NameRef").field(&self.to_string()).finish()
+    }
+}
+
+/// Writes the name like [`DomainName`] does
+impl std::fmt::Display for NameRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_root() {
+            return f.write_str(".");
+        }
+        for (idx, octets) in self.labels().enumerate() {
+            if idx > 0 {
+                f.write_str(".")?;
+            }
+            Label::fmt_octets(octets, f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterates over the labels of a [`NameRef`], following its compression pointers
+#[derive(Debug, Clone)]
+pub struct NameRefLabels<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Iterator for NameRefLabels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let size = *self.message.get(self.position)?;
+            match size {
+                size if DomainName::is_compressed(size) => {
+                    let second = self.message[self.position + 1];
+                    self.position =
+                        u16::from_be_bytes([size & !DomainName::POINTER_MASK, second]).into();
+                }
+                DomainName::TERMINATOR => return None,
+                size => {
+                    let start = self.position + 1;
+                    self.position = start + size as usize;
+                    return Some(&self.message[start..self.position]);
+                }
+            }
+        }
     }
 }
 
@@ -206,6 +696,39 @@ pub enum Error {
     /// Stores an error encountered while using [std::io] traits and structs
     #[error("Failed to parse domain name data:\n\t{0}")]
     Io(#[from] std::io::Error),
+    /// A label of the text form could not be used
+    #[error("Invalid label {label:?}:\n\t{source}")]
+    InvalidLabel {
+        label: String,
+        #[source]
+        source: label::Error,
+    },
+    /// Names take up at most [`DomainName::MAX_NAME_SIZE`] octets, including length octets
+    #[error(
+        "Name of {size} octets exceeds the maximum of {}",
+        DomainName::MAX_NAME_SIZE
+    )]
+    NameTooLong { size: usize },
+    /// A compression pointer does not point before the labels read since the last pointer,
+    /// which could make pointers loop
+    #[error("Compression pointer at {position} points forward to {offset}")]
+    ForwardPointer { position: u64, offset: u16 },
+    /// More than [`DomainName::MAX_POINTER_HOPS`] compression pointers were followed
+    #[error("Too many compression pointers followed, the last at {position}")]
+    TooManyPointers { position: u64 },
+    /// A backslash in the text form is not followed by a character or three decimal digits up to 255
+    #[error("Invalid escape at {position} in {name:?}")]
+    InvalidEscape { name: String, position: usize },
+    /// A name with non-ASCII characters could not be converted to A-labels
+    #[error("Invalid internationalized name {name:?}: {source}")]
+    Idna {
+        name: String,
+        #[source]
+        source: idna::Errors,
+    },
+    /// The length octet holds one of the reserved label types `01` or `10` in its high bits
+    #[error("Length octet {size:#04x} has a reserved label type")]
+    ReservedLabelType { size: u8 },
 }
 
 #[cfg(test)]
@@ -235,4 +758,244 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn subdomains() {
+        let www = DomainName::new("www.example.com");
+        assert!(www.is_subdomain_of(&DomainName::new("example.com")));
+        assert!(www.is_subdomain_of(&www));
+        assert!(www.is_subdomain_of(&DomainName::root()));
+        assert!(!www.is_subdomain_of(&DomainName::new("ample.com")));
+        assert!(!DomainName::new("example.com").is_subdomain_of(&www));
+    }
+
+    #[test]
+    fn suffix_replaced() {
+        let (owner, target) = (
+            DomainName::new("example.com"),
+            DomainName::new("example.net"),
+        );
+        assert_eq!(
+            DomainName::new("a.www.example.com").replace_suffix(&owner, &target),
+            Some(DomainName::new("a.www.example.net"))
+        );
+        // the owner itself is not redirected, nor are names elsewhere
+        assert_eq!(owner.replace_suffix(&owner, &target), None);
+        assert_eq!(target.replace_suffix(&owner, &target), None);
+
+        let long = DomainName::new(&vec!["a".repeat(63); 3].join("."));
+        assert_eq!(
+            DomainName::new("www.example.com").replace_suffix(&owner, &long),
+            Some(DomainName::new(&format!("www.{long}")))
+        );
+        assert_eq!(
+            DomainName::new(&format!("{}.example.com", "b".repeat(63)))
+                .replace_suffix(&owner, &long),
+            None
+        );
+    }
+
+    #[test]
+    fn case_insensitive_dname() {
+        use std::collections::HashSet;
+
+        let lower = DomainName::new("www.example.com");
+        let mixed = DomainName::new("WwW.ExAmple.COM");
+        assert_eq!(lower, mixed);
+        assert!(!lower.eq_exact(&mixed));
+        assert!(HashSet::from([lower.clone()]).contains(&mixed));
+
+        let randomized = lower.randomize_case();
+        assert_eq!(randomized, lower);
+        assert_eq!(randomized.to_string().to_lowercase(), lower.to_string());
+    }
+
+    #[test]
+    fn pointer_loops_rejected() {
+        // pointing at itself
+        let bytes = b"\xc0\x00";
+        assert!(matches!(
+            DomainName::from_bytes(&mut Cursor::new(&bytes[..])),
+            Err(Error::ForwardPointer {
+                position: 0,
+                offset: 0
+            })
+        ));
+
+        // pointing back to the label before it, which leads to the pointer again
+        let bytes = b"\x01a\xc0\x00";
+        assert!(matches!(
+            DomainName::from_bytes(&mut Cursor::new(&bytes[..])),
+            Err(Error::ForwardPointer {
+                position: 2,
+                offset: 0
+            })
+        ));
+
+        // a long chain of pointers, each pointing to the one before
+        let mut bytes = vec![DomainName::TERMINATOR, 0];
+        for hop in 1..=200u16 {
+            bytes.extend_from_slice(&(0xc000 | ((hop - 1) * 2)).to_be_bytes());
+        }
+        let mut cursor = Cursor::new(&bytes[..]);
+        cursor.set_position(bytes.len() as u64 - 2);
+        assert!(matches!(
+            DomainName::from_bytes(&mut cursor),
+            Err(Error::TooManyPointers { .. })
+        ));
+    }
+
+    #[test]
+    fn pointer_followed_backward() -> Result<()> {
+        let bytes = b"\x01a\x00\x00\x01b\xc0\x00\xff";
+        let mut cursor = Cursor::new(&bytes[..]);
+        cursor.set_position(4);
+
+        assert_eq!(DomainName::from_bytes(&mut cursor)?, DomainName::new("b.a"));
+        // left after the pointer, not after the name it points to
+        assert_eq!(cursor.position(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn length_limits() {
+        let max_label = "a".repeat(Label::MAX_LABEL_SIZE);
+        assert!(DomainName::parse(&max_label).is_ok());
+        assert!(matches!(
+            DomainName::parse(&format!("{max_label}a.com")),
+            Err(Error::InvalidLabel {
+                source: label::Error::TooLong { len: 64 },
+                ..
+            })
+        ));
+        assert!(matches!(
+            DomainName::parse("a..b"),
+            Err(Error::InvalidLabel {
+                source: label::Error::Empty,
+                ..
+            })
+        ));
+
+        // four labels of 63 octets take up 4 * 64 + 1 = 257 octets
+        let long_name = [max_label.as_str(); 4].join(".");
+        assert!(matches!(
+            DomainName::parse(&long_name),
+            Err(Error::NameTooLong { size: 257 })
+        ));
+        let longest_name = [&max_label[..61], &max_label, &max_label, &max_label].join(".");
+        assert_eq!(DomainName::parse(&longest_name).unwrap().wire_size(), 255);
+
+        for size in [0x40, 0x80] {
+            assert!(matches!(
+                DomainName::from_bytes(&mut Cursor::new(&[size, b'a', 0][..])),
+                Err(Error::ReservedLabelType { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn escaped_labels() -> Result<()> {
+        // dots, spaces and octets that are not text may appear within labels
+        let bytes = b"\x0fLiving Room\\Tv.\x04_tcp\x02\xff\x00\x00";
+        let name = DomainName::from_bytes(&mut Cursor::new(&bytes[..]))?;
+        assert_eq!(name.num_labels(), 3);
+
+        let text = String::from(name.clone());
+        assert_eq!(text, r"Living\032Room\\Tv\.._tcp.\255\000");
+        assert_eq!(DomainName::parse(&text)?.into_bytes(), bytes);
+
+        assert_eq!(DomainName::parse(r"a\.b.")?.num_labels(), 1);
+        assert_eq!(DomainName::parse(r"\097")?, DomainName::new("a"));
+        for invalid in [r"a\", r"\25", r"\256"] {
+            assert!(matches!(
+                DomainName::parse(invalid),
+                Err(Error::InvalidEscape { .. })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn internationalized_names() -> Result<()> {
+        let name = DomainName::parse("München.DE")?;
+        assert_eq!(name.to_string(), "xn--mnchen-3ya.de");
+        assert_eq!(name, DomainName::new("xn--mnchen-3ya.de"));
+        assert_eq!(name.to_unicode(), "münchen.de");
+
+        // the ideographic full stop separates labels as well
+        let name = DomainName::parse("例え。テスト")?;
+        assert_eq!(name.num_labels(), 2);
+        assert_eq!(name.to_unicode(), "例え.テスト");
+
+        // labels that only look like A-labels are left as they are
+        assert_eq!(DomainName::new("xn--.com").to_unicode(), "xn--.com");
+        assert!(matches!(
+            DomainName::parse("a\u{fffd}b.com"),
+            Err(Error::Idna { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn root_and_trailing_dot() {
+        assert_eq!(
+            DomainName::new("example.com."),
+            DomainName::new("example.com")
+        );
+        assert_eq!(DomainName::new("."), DomainName::root());
+        assert_eq!(DomainName::new(""), DomainName::root());
+        assert_eq!(DomainName::root().to_string(), ".");
+        assert_eq!(DomainName::root().into_bytes(), b"\x00");
+        assert_eq!(DomainName::root().parent(), None);
+
+        let name = DomainName::new("www.example.com.");
+        assert_eq!(name.num_labels(), 3);
+        assert_eq!(
+            name.labels().rev().map(Label::as_bytes).collect::<Vec<_>>(),
+            [&b"com"[..], b"example", b"www"]
+        );
+        assert_eq!(name.parent(), Some(DomainName::new("example.com")));
+    }
+
+    /// Tests the example ordering of RFC 4034 section 6.1
+    #[test]
+    fn canonical_order() {
+        let ordered = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "*.z.example",
+        ]
+        .map(DomainName::new);
+
+        let mut sorted = ordered.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert!(sorted.iter().zip(&ordered).all(|(a, b)| a.eq_exact(b)));
+        assert!(DomainName::root() < DomainName::new("example"));
+    }
+
+    /// Tests that a repeated suffix is replaced by a pointer to its first occurrence
+    #[test]
+    fn encode_compressed_dname() -> Result<()> {
+        let mut compressor = Compressor::default();
+        let mut buf = Vec::new();
+
+        DomainName::new("www.google.com").write_compressed(&mut buf, &mut compressor);
+        DomainName::new("mail.google.com").write_compressed(&mut buf, &mut compressor);
+        DomainName::new("www.google.com").write_compressed(&mut buf, &mut compressor);
+
+        let correct_bytes = b"\x03www\x06google\x03com\x00\x04mail\xc0\x04\xc0\x00";
+        assert_eq!(buf, correct_bytes);
+
+        let mut reader = Cursor::new(&buf[..]);
+        for name in ["www.google.com", "mail.google.com", "www.google.com"] {
+            assert_eq!(DomainName::from_bytes(&mut reader)?, DomainName::new(name));
+        }
+
+        Ok(())
+    }
 }
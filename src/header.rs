@@ -101,14 +101,17 @@ use byteorder::{ByteOrder, NetworkEndian, ReadBytesExt};
 
 use thiserror::Error;
 
+use crate::{opcode::Opcode, rcode::Rcode};
+
 /// The header includes fields that specify which of the remaining sections are present,
 /// and also specifywhether the message is a query or a response, a standard query or some other opcode, etc.
-#[derive(Debug, Clone, Copy)] // TODO what other derives needed?
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Header {
     /// A 16 bit identifier assigned by the program that generates any kind of query.
     /// This identifier is copied the corresponding reply and can be used by the requester to match up replies to outstanding queries.
     pub id: u16,
-    pub flags: u16, // TODO bitflags?
+    /// The QR, OPCODE, AA, TC, RD, RA, Z, AD, CD and RCODE fields
+    pub flags: Flags,
     /// An unsigned 16 bit integer specifying the number of entries in the question section.
     pub num_questions: u16,
     /// An unsigned 16 bit integer specifying the number of resource records in the answer section.
@@ -127,7 +130,7 @@ impl Header {
         NetworkEndian::write_u16_into(
             &[
                 self.id,
-                self.flags,
+                self.flags.into(),
                 self.num_questions,
                 self.num_answers,
                 self.num_authorities,
@@ -147,7 +150,7 @@ impl Header {
 
         Ok(Self {
             id,
-            flags,
+            flags: Flags::from(flags),
             num_questions,
             num_answers,
             num_authorities,
@@ -156,6 +159,164 @@ impl Header {
     }
 }
 
+/// The second 16 bits of a [`Header`], holding the fields below
+///
+/// ```text
+///   0  1  2  3  4  5  6  7  8  9  10 11 12 13 14 15
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// |QR|   Opcode  |AA|TC|RD|RA| Z|AD|CD|   RCODE   |
+/// +--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+--+
+/// ```
+///
+/// The AD and CD bits were carved out of the Z field by [RFC 4035](https://datatracker.ietf.org/doc/html/rfc4035#section-3.2)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Flags(u16);
+
+impl Flags {
+    // endianness clarification: bit 0 in the diagram is the MSB of the 16 bit field.
+    const QR: u16 = 1 << 15;
+    const OPCODE_SHIFT: u16 = 11;
+    const OPCODE_MASK: u16 = 0b1111;
+    const AA: u16 = 1 << 10;
+    const TC: u16 = 1 << 9;
+    const RD: u16 = 1 << 8;
+    const RA: u16 = 1 << 7;
+    const Z: u16 = 1 << 6;
+    const AD: u16 = 1 << 5;
+    const CD: u16 = 1 << 4;
+    const RCODE_MASK: u16 = 0b1111;
+
+    /// Creates [`Flags`] with every field zeroed, i.e. a standard query
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    const fn with_bit(self, bit: u16, value: bool) -> Self {
+        if value {
+            Self(self.0 | bit)
+        } else {
+            Self(self.0 & !bit)
+        }
+    }
+
+    const fn has_bit(self, bit: u16) -> bool {
+        self.0 & bit != 0
+    }
+
+    /// Whether this message is a response (QR)
+    pub const fn is_response(self) -> bool {
+        self.has_bit(Self::QR)
+    }
+
+    /// The kind of query in this message (OPCODE)
+    pub fn opcode(self) -> Opcode {
+        Opcode::from(((self.0 >> Self::OPCODE_SHIFT) & Self::OPCODE_MASK) as u8)
+    }
+
+    /// Whether the responding server is an authority for the domain name in question (AA)
+    pub const fn is_authoritative(self) -> bool {
+        self.has_bit(Self::AA)
+    }
+
+    /// Whether this message was truncated to fit the transmission channel (TC)
+    pub const fn is_truncated(self) -> bool {
+        self.has_bit(Self::TC)
+    }
+
+    /// Whether the name server should pursue the query recursively (RD)
+    pub const fn recursion_desired(self) -> bool {
+        self.has_bit(Self::RD)
+    }
+
+    /// Whether the name server supports recursive queries (RA)
+    pub const fn recursion_available(self) -> bool {
+        self.has_bit(Self::RA)
+    }
+
+    /// The reserved bit, which must be zero in all queries and responses (Z)
+    pub const fn z(self) -> bool {
+        self.has_bit(Self::Z)
+    }
+
+    /// Whether the server considers all data in the answer and authority sections authentic (AD)
+    pub const fn authentic_data(self) -> bool {
+        self.has_bit(Self::AD)
+    }
+
+    /// Whether the requester accepts data the server has not verified (CD)
+    pub const fn checking_disabled(self) -> bool {
+        self.has_bit(Self::CD)
+    }
+
+    /// The lower 4 bits of the response code (RCODE)
+    pub fn rcode(self) -> Rcode {
+        Rcode::from(self.0 & Self::RCODE_MASK)
+    }
+
+    /// Sets the QR bit
+    pub const fn with_response(self, value: bool) -> Self {
+        self.with_bit(Self::QR, value)
+    }
+
+    /// Sets the OPCODE field
+    pub fn with_opcode(self, opcode: Opcode) -> Self {
+        let opcode = (u8::from(opcode) as u16 & Self::OPCODE_MASK) << Self::OPCODE_SHIFT;
+        Self(self.0 & !(Self::OPCODE_MASK << Self::OPCODE_SHIFT) | opcode)
+    }
+
+    /// Sets the AA bit
+    pub const fn with_authoritative(self, value: bool) -> Self {
+        self.with_bit(Self::AA, value)
+    }
+
+    /// Sets the TC bit
+    pub const fn with_truncated(self, value: bool) -> Self {
+        self.with_bit(Self::TC, value)
+    }
+
+    /// Sets the RD bit
+    pub const fn with_recursion_desired(self, value: bool) -> Self {
+        self.with_bit(Self::RD, value)
+    }
+
+    /// Sets the RA bit
+    pub const fn with_recursion_available(self, value: bool) -> Self {
+        self.with_bit(Self::RA, value)
+    }
+
+    /// Sets the reserved Z bit
+    pub const fn with_z(self, value: bool) -> Self {
+        self.with_bit(Self::Z, value)
+    }
+
+    /// Sets the AD bit
+    pub const fn with_authentic_data(self, value: bool) -> Self {
+        self.with_bit(Self::AD, value)
+    }
+
+    /// Sets the CD bit
+    pub const fn with_checking_disabled(self, value: bool) -> Self {
+        self.with_bit(Self::CD, value)
+    }
+
+    /// Sets the RCODE field to the lower 4 bits of `rcode`
+    pub fn with_rcode(self, rcode: Rcode) -> Self {
+        Self(self.0 & !Self::RCODE_MASK | (u16::from(rcode) & Self::RCODE_MASK))
+    }
+}
+
+impl From<u16> for Flags {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Flags> for u16 {
+    fn from(value: Flags) -> Self {
+        value.0
+    }
+}
+
 /// Wraps the errors that may be encountered during byte decoding of a [`Header`]
 #[derive(Debug, Error)]
 pub enum Error {
@@ -174,7 +335,7 @@ mod tests {
     fn encode_header() {
         let header = Header {
             id: 0x1314,
-            flags: 0,
+            flags: Flags::new(),
             num_questions: 1,
             num_answers: 0,
             num_authorities: 0,
@@ -202,13 +363,13 @@ mod tests {
 
         let expected_id = 0x8298;
 
-        // recursion desired
-        let expected_flags: u16 = 1 << 8;
+        let expected_flags = Flags::new().with_recursion_desired(true);
 
         let result_header = Header::from_bytes(&mut Cursor::new(&test_bytes))?;
 
         assert_eq!(result_header.id, expected_id);
         assert_eq!(result_header.flags, expected_flags);
+        assert!(result_header.flags.recursion_desired());
         assert_eq!(result_header.num_questions, 1);
         assert_eq!(result_header.num_answers, 0);
         assert_eq!(result_header.num_authorities, 0);
@@ -216,4 +377,34 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn header_flags() {
+        // a recursive NXDOMAIN response to a standard query
+        let flags = Flags::from(0x8183);
+
+        assert!(flags.is_response());
+        assert_eq!(flags.opcode(), Opcode::QUERY);
+        assert!(!flags.is_authoritative());
+        assert!(!flags.is_truncated());
+        assert!(flags.recursion_desired());
+        assert!(flags.recursion_available());
+        assert!(!flags.authentic_data());
+        assert_eq!(flags.rcode(), Rcode::NXDOMAIN);
+
+        let built = Flags::new()
+            .with_response(true)
+            .with_recursion_desired(true)
+            .with_recursion_available(true)
+            .with_rcode(Rcode::NXDOMAIN);
+        assert_eq!(built, flags);
+
+        let update = flags
+            .with_opcode(Opcode::UPDATE)
+            .with_authentic_data(true)
+            .with_checking_disabled(true)
+            .with_rcode(Rcode::NOERROR);
+        assert_eq!(u16::from(update), 0xA9B0);
+        assert_eq!(update.opcode(), Opcode::UPDATE);
+    }
 }
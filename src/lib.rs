@@ -1,27 +1,52 @@
-mod dname;
-mod header;
-mod message;
-mod qclass;
-mod qtype;
-mod question;
-mod record;
-
-use std::{
-    io::Cursor,
-    net::{ToSocketAddrs, UdpSocket},
-};
+pub mod cache;
+pub mod dname;
+pub mod header;
+pub mod message;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod opcode;
+pub mod qclass;
+pub mod qtype;
+pub mod question;
+pub mod rcode;
+pub mod rdata;
+pub mod record;
+pub mod resolver;
+#[cfg(test)]
+mod test_util;
+pub mod transport;
 
 use rand::Rng;
 
+pub use crate::resolver::{lookup_ip, resolve, Lookup, ResolveError, Resolver, ResolverConfig};
 use crate::{
     dname::DomainName,
-    header::Header,
-    message::{Message, MsgSection},
+    header::{Flags, Header},
+    message::Message,
     qtype::QType,
     question::Question,
+    record::Edns,
 };
 
-pub fn build_query(domain_name: &str, record_type: QType, flags: u16) -> Vec<u8> {
+/// Builds the bytes of a query for `domain_name`'s records of type `record_type`, with a random ID
+///
+/// Fails if `domain_name` is not a valid name, see [`DomainName::parse`]
+pub fn build_query(
+    domain_name: &str,
+    record_type: QType,
+    flags: Flags,
+    edns: Option<Edns>,
+) -> message::Result<Vec<u8>> {
+    let name = DomainName::parse(domain_name)?;
+    query_message(name, record_type, flags, edns).to_bytes()
+}
+
+pub(crate) fn query_message(
+    name: DomainName,
+    record_type: QType,
+    flags: Flags,
+    edns: Option<Edns>,
+) -> Message {
     let id: u16 = rand::thread_rng().gen();
     let header = Header {
         id,
@@ -32,103 +57,33 @@ pub fn build_query(domain_name: &str, record_type: QType, flags: u16) -> Vec<u8>
         num_additionals: 0,
     };
 
-    let name = DomainName::new(domain_name);
     let question = Question {
         qname: name,
         qclass: qclass::QClass::IN,
         qtype: record_type,
     };
 
-    let mut header_bytes = header.into_bytes();
-    let mut question_bytes = question.into_bytes();
-    let mut buf = Vec::with_capacity(header_bytes.len() + question_bytes.len());
-    buf.append(&mut header_bytes);
-    buf.append(&mut question_bytes);
-    buf
-}
-
-/// Returns a ready-to-use UDP socket connected to the given address
-fn setup_udp_socket_to(dns_server_addr: impl ToSocketAddrs) -> std::io::Result<UdpSocket> {
-    let udp_sock = UdpSocket::bind((std::net::Ipv4Addr::UNSPECIFIED, 0))?;
-    udp_sock.connect(dns_server_addr)?;
-    Ok(udp_sock)
-}
-
-fn send_query(
-    desired_addr: &str,
-    server_addr: std::net::IpAddr,
-    record_type: QType,
-) -> message::Result<Message> {
-    let query = build_query(desired_addr, record_type, 0);
-
-    let socket_addr = std::net::SocketAddr::from((server_addr, 53));
-
-    // connection setup
-    let udp_sock = setup_udp_socket_to(socket_addr)?;
-
-    // query request
-    udp_sock.send(&query)?;
-
-    // get response
-    let mut recv_buf = [0u8; 1024];
-    let bytes_recv = udp_sock.recv(&mut recv_buf)?;
-
-    // parse response to message
-    let mut msg_bytes_reader = Cursor::new(&recv_buf[..bytes_recv]);
-
-    Message::from_bytes(&mut msg_bytes_reader)
-}
-
-pub fn lookup_domain(domain_name: &str) -> message::Result<std::net::IpAddr> {
-    resolve(domain_name, QType::A)
-}
-
-pub fn resolve(domain_name: &str, record_type: QType) -> message::Result<std::net::IpAddr> {
-    let mut nameserver = "198.41.0.4".parse::<std::net::IpAddr>().unwrap();
-    loop {
-        println!("Querying {nameserver} for {domain_name}");
-        let resp = send_query(domain_name, nameserver, record_type)?;
-
-        if let Some(domain_ip_rr) = resp.get_record_by_type_from(QType::A, MsgSection::Answers) {
-            return Ok(domain_ip_rr.data_as_ip_addr());
-        } else if let Some(ns_ip_rr) =
-            resp.get_record_by_type_from(QType::A, MsgSection::Additionals)
-        {
-            nameserver = ns_ip_rr.data_as_ip_addr();
-        } else if let Some(ns_dname_rr) =
-            resp.get_record_by_type_from(QType::NS, MsgSection::Authorities)
-        {
-            nameserver = resolve(ns_dname_rr.data_as_str(), record_type)?;
-        } else if let Some(cname_rr) =
-            resp.get_record_by_type_from(QType::CNAME, MsgSection::Answers)
-        {
-            return resolve(cname_rr.data_as_str(), record_type);
-        } else {
-            panic!("Unexpected resolver error\nreceived: {resp:#?}")
-        }
+    Message {
+        header,
+        questions: vec![question],
+        answers: Vec::new(),
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns,
     }
 }
 
-fn print_bytes_as_hex(bytes: &[u8]) {
-    eprint!("0x");
-    for b in bytes {
-        eprint!("{b:02X?}")
-    }
-    eprintln!();
-}
-
 #[cfg(test)]
 mod tests {
-    use crate::*;
+    use crate::{test_util::record, transport::setup_udp_socket_to, *};
 
-    // endianness clarification: 7th MSB of the 3rd octet is 9 bits away from bit 15.
-    const RECURSION_DESIRED: u16 = 1 << 8;
+    const RECURSION_DESIRED: Flags = Flags::new().with_recursion_desired(true);
 
     #[test]
-    fn test_build_query() -> std::fmt::Result {
+    fn test_build_query() -> Result<(), Box<dyn std::error::Error>> {
         let correct_bytes_str =
             "82980100000100000000000003777777076578616d706c6503636f6d0000010001";
-        let query_bytes = build_query("www.example.com", qtype::QType::A, RECURSION_DESIRED);
+        let query_bytes = build_query("www.example.com", qtype::QType::A, RECURSION_DESIRED, None)?;
 
         let mut query_bytes_str = String::with_capacity(correct_bytes_str.len());
 
@@ -149,8 +104,14 @@ mod tests {
     }
 
     #[test]
-    fn test_send_query() -> std::io::Result<()> {
-        let query_bytes = build_query("www.example.com", qtype::QType::A, RECURSION_DESIRED);
+    fn build_query_rejects_invalid_name() {
+        let result = build_query("www..example.com", qtype::QType::A, RECURSION_DESIRED, None);
+        assert!(matches!(result, Err(message::Error::Name(_))));
+    }
+
+    #[test]
+    fn test_send_query() -> message::Result<()> {
+        let query_bytes = build_query("www.example.com", qtype::QType::A, RECURSION_DESIRED, None)?;
 
         // connection setup
         let udp_sock = setup_udp_socket_to("8.8.8.8:53").expect("Failed to setup UDP socket");
@@ -161,18 +122,76 @@ mod tests {
         Ok(())
     }
 
+    /// A resolver starting from the real root hints, answered by a mock of the root, com and two of its zones
+    fn mock_resolver() -> Resolver {
+        use rdata::RData::{A, CNAME, NS};
+        let ip = |addr: &str| addr.parse::<std::net::IpAddr>().unwrap();
+        let ns = |name: &str| NS(DomainName::new(name));
+
+        let mock = mock::Mock::new();
+        mock.serve_zone(
+            ip("198.41.0.4"),
+            DomainName::root(),
+            vec![
+                record("com", 3600, ns("a.gtld-servers.net")),
+                record("a.gtld-servers.net", 3600, A("192.5.6.30".parse().unwrap())),
+            ],
+        );
+        mock.serve_zone(
+            ip("192.5.6.30"),
+            DomainName::new("com"),
+            vec![
+                record("example.com", 3600, ns("ns.example.com")),
+                record("ns.example.com", 3600, A("199.43.135.53".parse().unwrap())),
+                record("facebook.com", 3600, ns("a.ns.facebook.com")),
+                record(
+                    "a.ns.facebook.com",
+                    3600,
+                    A("129.134.30.12".parse().unwrap()),
+                ),
+            ],
+        );
+        mock.serve_zone(
+            ip("199.43.135.53"),
+            DomainName::new("example.com"),
+            vec![record(
+                "www.example.com",
+                3600,
+                A("93.184.216.34".parse().unwrap()),
+            )],
+        );
+        mock.serve_zone(
+            ip("129.134.30.12"),
+            DomainName::new("facebook.com"),
+            vec![
+                record(
+                    "www.facebook.com",
+                    3600,
+                    CNAME(DomainName::new("star-mini.c10r.facebook.com")),
+                ),
+                record(
+                    "star-mini.c10r.facebook.com",
+                    3600,
+                    A("157.240.1.35".parse().unwrap()),
+                ),
+            ],
+        );
+        Resolver::with_transport(ResolverConfig::default(), std::sync::Arc::new(mock))
+    }
+
     #[test]
-    fn test_resolve() -> Result<(), message::Error> {
-        let result_ip = resolve("www.example.com", QType::A)?;
-        let correct_ip = "93.184.216.34".parse::<std::net::Ipv4Addr>().unwrap();
-        assert_eq!(result_ip, correct_ip);
+    fn test_resolve() -> resolver::Result<()> {
+        let lookup = mock_resolver().resolve("www.example.com", QType::A)?;
+        let correct_ip = "93.184.216.34".parse::<std::net::IpAddr>().unwrap();
+        assert!(lookup.ip_addrs().any(|ip| ip == correct_ip));
         Ok(())
     }
 
     #[test]
-    fn test_cname() -> message::Result<()> {
-        // facebook has multiple IP addrs, no sense checking for any possible one.
-        let _ = lookup_domain("www.facebook.com")?;
+    fn test_cname() -> resolver::Result<()> {
+        let lookup = mock_resolver().resolve("www.facebook.com", QType::A)?;
+        assert!(!lookup.cname_chain.is_empty());
+        assert!(lookup.ip_addrs().next().is_some());
         Ok(())
     }
 }
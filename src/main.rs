@@ -1,17 +1,124 @@
+use std::{net::IpAddr, time::Duration};
+
 use clap::Parser;
+use dirt::{
+    dname::DomainName, qtype::QType, rdata::RData, resolver::IpPreference, transport::Protocol,
+    Resolver, ResolverConfig,
+};
 
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Arguments {
-    /// Requested domain name
+    /// Requested domain name, which may hold Unicode characters
     request: String,
+    /// Requested record type, by name (e.g. AAAA) or as TYPE followed by its value (e.g. TYPE65)
+    #[arg(long = "type", default_value_t = QType::A)]
+    qtype: QType,
+    /// Send every query over TCP instead of UDP
+    #[arg(long)]
+    tcp: bool,
+    /// Only contact name servers over IPv4
+    #[arg(short = '4', conflicts_with_all = ["ipv6", "prefer_ipv6"])]
+    ipv4: bool,
+    /// Only contact name servers over IPv6
+    #[arg(short = '6', conflicts_with = "prefer_ipv6")]
+    ipv6: bool,
+    /// Contact name servers over IPv6 when they have an IPv6 address, rather than over IPv4
+    #[arg(long)]
+    prefer_ipv6: bool,
+    /// Seconds to wait for the first response from each name server
+    #[arg(long, default_value_t = 2)]
+    timeout: u64,
+    /// How many more rounds of queries are sent after every name server of a zone failed to answer
+    #[arg(long, default_value_t = 2)]
+    retries: usize,
+    /// Start resolution from this server instead of the root servers, may be repeated
+    #[arg(long = "root")]
+    roots: Vec<IpAddr>,
+    /// Randomize the case of each query's name, only accepting responses that echo it exactly
+    #[arg(long)]
+    randomize_case: bool,
+    /// Print every server queried and every record rejected on the way to the answer
+    #[arg(long)]
+    trace: bool,
+    /// Print internationalized names in their Unicode form instead of their "xn--" form
+    #[arg(long)]
+    unicode: bool,
 }
 
 fn main() {
     let args = Arguments::parse();
 
-    match dirt::lookup_domain(&args.request) {
-        Ok(ip) => println!("{ip}"),
+    let protocol = if args.tcp {
+        Protocol::Tcp
+    } else {
+        Protocol::Udp
+    };
+
+    let ip_preference = match (args.ipv4, args.ipv6, args.prefer_ipv6) {
+        (true, _, _) => IpPreference::Ipv4Only,
+        (_, true, _) => IpPreference::Ipv6Only,
+        (_, _, true) => IpPreference::Ipv6First,
+        _ => IpPreference::Ipv4First,
+    };
+
+    let mut config = ResolverConfig {
+        protocol,
+        ip_preference,
+        timeout: Duration::from_secs(args.timeout),
+        retries: args.retries,
+        randomize_case: args.randomize_case,
+        ..ResolverConfig::default()
+    };
+    if !args.roots.is_empty() {
+        config.root_hints = args.roots;
+    }
+
+    let show = |name: &DomainName| {
+        if args.unicode {
+            name.to_unicode()
+        } else {
+            name.to_string()
+        }
+    };
+
+    match Resolver::new(config).resolve(&args.request, args.qtype) {
+        Ok(lookup) => {
+            if args.trace {
+                for server in &lookup.trace.servers {
+                    println!("queried {server}");
+                }
+                for rejected in &lookup.trace.rejected {
+                    println!(
+                        "rejected {} {} from {}, outside {}",
+                        show(&rejected.record.name),
+                        rejected.record.qtype,
+                        rejected.server,
+                        show(&rejected.zone)
+                    );
+                }
+            }
+            for dname in &lookup.dnames {
+                if let Some(target) = dname.rdata.as_name() {
+                    println!(
+                        "names beneath {} are redirected to {}",
+                        show(&dname.name),
+                        show(target)
+                    );
+                }
+            }
+            for cname in &lookup.cname_chain {
+                match &cname.rdata {
+                    RData::CNAME(target) => {
+                        println!("{} is an alias for {}", show(&cname.name), show(target))
+                    }
+                    rdata => println!("{} is an alias for {rdata}", show(&cname.name)),
+                }
+            }
+            for record in &lookup.records {
+                println!("{}", record.rdata);
+            }
+        }
         Err(e) => eprintln!("{e}"),
     }
 }
@@ -1,6 +1,13 @@
 use std::io::Cursor;
 
-use crate::{header::Header, qtype::QType, question::Question, record::Record};
+use crate::{
+    dname::Compressor,
+    header::Header,
+    qtype::QType,
+    question::{Question, QuestionRef},
+    rcode::Rcode,
+    record::{Edns, Record, RecordRef},
+};
 
 /// All communications inside of the domain protocol are carried in a single format called a message.
 ///
@@ -19,7 +26,7 @@ use crate::{header::Header, qtype::QType, question::Question, record::Record};
 /// |      Additional     | RRs holding additional information
 /// +---------------------+
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Message {
     pub header: Header,
     /// The query name(s) and other query parameters.
@@ -31,7 +38,11 @@ pub struct Message {
     /// May optionally carry the SOA RR for the authoritative data in the answer section
     pub authorities: Vec<Record>,
     /// RRs which may be helpful in using the RRs in the other sections.
+    ///
+    /// Does not hold the OPT pseudo-record, see [`Message::edns`]
     pub additionals: Vec<Record>,
+    /// The EDNS(0) OPT pseudo-record, carried at the end of the additional section on the wire.
+    pub edns: Option<Edns>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -58,9 +69,16 @@ impl Message {
             .take(header.num_authorities as usize)
             .collect::<std::result::Result<Vec<Record>, crate::record::Error>>()?;
 
-        let additionals: Vec<Record> = std::iter::repeat_with(|| Record::from_bytes(bytes))
-            .take(header.num_additionals as usize)
-            .collect::<std::result::Result<Vec<Record>, crate::record::Error>>()?;
+        let mut additionals = Vec::with_capacity(header.num_additionals as usize);
+        let mut edns = None;
+        for _ in 0..header.num_additionals {
+            if !Edns::is_next(bytes)? {
+                additionals.push(Record::from_bytes(bytes)?);
+            } else if edns.replace(Edns::from_bytes(bytes)?).is_some() {
+                // RFC 6891 6.1.1: more than one OPT record is a format error
+                return Err(Error::MultipleOpt);
+            }
+        }
 
         Ok(Self {
             header,
@@ -68,12 +86,69 @@ impl Message {
             answers,
             authorities,
             additionals,
+            edns,
         })
     }
+
+    /// Converts a [`Message`] to owned bytes
+    ///
+    /// The section counts in the header are taken from the sections themselves,
+    /// and repeated domain names are compressed as described in
+    /// [RFC 1035 section 4.1.4](https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.4).
+    ///
+    /// Fails if a record's data cannot be encoded, see [`RData::write_to`](crate::rdata::RData::write_to),
+    /// or if a section holds more entries than its 16 bit count can tell
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let count =
+            |count: usize| u16::try_from(count).map_err(|_| Error::SectionTooLong { count });
+        let header = Header {
+            num_questions: count(self.questions.len())?,
+            num_answers: count(self.answers.len())?,
+            num_authorities: count(self.authorities.len())?,
+            num_additionals: count(self.additionals.len() + usize::from(self.edns.is_some()))?,
+            ..self.header
+        };
+
+        let mut buf = header.into_bytes();
+        let mut compressor = Compressor::default();
+
+        for question in &self.questions {
+            question.write_to(&mut buf, &mut compressor);
+        }
+
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            record.write_to(&mut buf, &mut compressor)?;
+        }
+
+        if let Some(edns) = &self.edns {
+            edns.write_to(&mut buf)?;
+        }
+
+        Ok(buf)
+    }
 }
 
 // querying data
 impl Message {
+    /// The EDNS(0) parameters of the sender, if it included an OPT record
+    pub fn edns(&self) -> Option<&Edns> {
+        self.edns.as_ref()
+    }
+
+    /// The response code, including the extended bits of the OPT record if present
+    pub fn rcode(&self) -> Rcode {
+        let header_rcode = self.header.flags.rcode();
+        match &self.edns {
+            Some(edns) => edns.rcode(header_rcode),
+            None => header_rcode,
+        }
+    }
+
     pub fn get_records(&self, section: MsgSection) -> &[Record] {
         match section {
             MsgSection::Answers => &self.answers,
@@ -89,6 +164,94 @@ impl Message {
     }
 }
 
+/// A [`Message`] read in place from a slice of bytes, without copying any of its contents.
+///
+/// Reading it only checks that its sections are well framed, i.e. that every name, question and
+/// record ends within the message. Names and record data are decoded as they are iterated over,
+/// and [`MessageRef::to_message`] converts the whole message when an owned one is needed.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageRef<'a> {
+    bytes: &'a [u8],
+    header: Header,
+    /// where the question, answer, authority and additional sections start
+    sections: [usize; 4],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Reads a [`MessageRef`] from a sequence of bytes
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(bytes);
+        let header = Header::from_bytes(&mut cursor)?;
+
+        let mut sections = [0; 4];
+        let mut position = cursor.position() as usize;
+
+        sections[0] = position;
+        for _ in 0..header.num_questions {
+            position = QuestionRef::read(bytes, position)?.1;
+        }
+        let counts = [
+            header.num_answers,
+            header.num_authorities,
+            header.num_additionals,
+        ];
+        for (section, count) in counts.into_iter().enumerate() {
+            sections[section + 1] = position;
+            for _ in 0..count {
+                position = RecordRef::read(bytes, position)?.1;
+            }
+        }
+
+        Ok(Self {
+            bytes,
+            header,
+            sections,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The bytes the message was read from
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn questions(&self) -> impl ExactSizeIterator<Item = QuestionRef<'a>> {
+        let bytes = self.bytes;
+        let mut position = self.sections[0];
+        (0..self.header.num_questions as usize).map(move |_| {
+            // the framing was checked when the message was read
+            let (question, end) = QuestionRef::read(bytes, position).expect("checked question");
+            position = end;
+            question
+        })
+    }
+
+    /// The records of a section, including the OPT pseudo-record among the additionals
+    pub fn records(&self, section: MsgSection) -> impl ExactSizeIterator<Item = RecordRef<'a>> {
+        let (start, count) = match section {
+            MsgSection::Answers => (self.sections[1], self.header.num_answers),
+            MsgSection::Authorities => (self.sections[2], self.header.num_authorities),
+            MsgSection::Additionals => (self.sections[3], self.header.num_additionals),
+        };
+        let bytes = self.bytes;
+        let mut position = start;
+        (0..count as usize).map(move |_| {
+            // the framing was checked when the message was read
+            let (record, end) = RecordRef::read(bytes, position).expect("checked record");
+            position = end;
+            record
+        })
+    }
+
+    /// Decodes the whole message into an owned [`Message`]
+    pub fn to_message(&self) -> Result<Message> {
+        Message::from_bytes(&mut Cursor::new(self.bytes))
+    }
+}
+
 /// Wraps the errors that may be encountered during byte decoding of a [`Message`]
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -104,6 +267,235 @@ pub enum Error {
     /// Encountered during record parsing
     #[error(transparent)]
     Record(#[from] crate::record::Error),
+    /// The name to build a message for is not a valid domain name
+    #[error(transparent)]
+    Name(#[from] crate::dname::Error),
+    /// The additional section held more than one OPT record
+    #[error("Message holds more than one OPT record")]
+    MultipleOpt,
+    /// A section held more entries than fit its count in the header
+    #[error("Message section of {count} entries exceeds the maximum of 65535")]
+    SectionTooLong { count: usize },
+    /// The message is longer than the length prefixed to it over TCP can tell
+    #[error("Message of {length} octets exceeds the maximum of 65535")]
+    TooLong { length: usize },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dname::DomainName, header::Flags, qclass::QClass, rdata::RData, record::EdnsOption,
+    };
+
+    #[test]
+    fn encode_message() -> Result<()> {
+        let name = DomainName::new("www.example.com");
+        let record = |rdata: RData| Record {
+            name: name.clone(),
+            qtype: rdata.rtype(),
+            class: QClass::IN,
+            time_to_live: 300,
+            rdata,
+        };
+
+        let message = Message {
+            header: Header {
+                id: 0xbeef,
+                flags: Flags::new()
+                    .with_response(true)
+                    .with_recursion_desired(true)
+                    .with_recursion_available(true),
+                num_questions: 1,
+                num_answers: 2,
+                num_authorities: 1,
+                num_additionals: 0,
+            },
+            questions: vec![Question {
+                qname: name.clone(),
+                qtype: QType::A,
+                qclass: QClass::IN,
+            }],
+            answers: vec![
+                record(RData::CNAME(DomainName::new("web.example.com"))),
+                Record {
+                    name: DomainName::new("web.example.com"),
+                    ..record(RData::A(std::net::Ipv4Addr::new(93, 184, 216, 34)))
+                },
+            ],
+            authorities: vec![Record {
+                name: DomainName::new("example.com"),
+                ..record(RData::NS(DomainName::new("ns.example.com")))
+            }],
+            additionals: vec![],
+            edns: None,
+        };
+
+        let bytes = message.to_bytes()?;
+
+        let correct_bytes = b"\xbe\xef\x81\x80\x00\x01\x00\x02\x00\x01\x00\x00\
+            \x03www\x07example\x03com\x00\x00\x01\x00\x01\
+            \xc0\x0c\x00\x05\x00\x01\x00\x00\x01\x2c\x00\x06\x03web\xc0\x10\
+            \xc0\x2d\x00\x01\x00\x01\x00\x00\x01\x2c\x00\x04\x5d\xb8\xd8\x22\
+            \xc0\x10\x00\x02\x00\x01\x00\x00\x01\x2c\x00\x05\x02ns\xc0\x10";
+        assert_eq!(bytes, correct_bytes);
+
+        let decoded = Message::from_bytes(&mut Cursor::new(&bytes[..]))?;
+        assert_eq!(decoded, message);
+
+        Ok(())
+    }
+
+    #[test]
+    fn oversized_section_rejected() {
+        let record = Record {
+            name: DomainName::new("www.example.com"),
+            qtype: QType::A,
+            class: QClass::IN,
+            time_to_live: 300,
+            rdata: RData::A(std::net::Ipv4Addr::new(192, 0, 2, 1)),
+        };
+        let message = Message {
+            header: Header {
+                id: 0xbeef,
+                flags: Flags::new().with_response(true),
+                num_questions: 0,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 0,
+            },
+            questions: vec![],
+            answers: vec![record; usize::from(u16::MAX) + 1],
+            authorities: vec![],
+            additionals: vec![],
+            edns: None,
+        };
+
+        let err = message.to_bytes().unwrap_err();
+        assert!(
+            matches!(err, Error::SectionTooLong { count: 65536 }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn edns_message() -> Result<()> {
+        let edns = Edns {
+            udp_payload_size: 4096,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+            options: vec![
+                EdnsOption::Cookie {
+                    client: *b"\x01\x02\x03\x04\x05\x06\x07\x08",
+                    server: Vec::new(),
+                },
+                EdnsOption::ClientSubnet {
+                    source_prefix: 24,
+                    scope_prefix: 0,
+                    address: "192.0.2.0".parse().unwrap(),
+                },
+                EdnsOption::Padding(4),
+            ],
+        };
+
+        let message = Message {
+            header: Header {
+                id: 0x1234,
+                flags: Flags::new().with_response(true),
+                num_questions: 0,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 1,
+            },
+            questions: vec![],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+            edns: Some(edns),
+        };
+
+        let bytes = message.to_bytes()?;
+
+        let correct_opt = b"\x00\x00\x29\x10\x00\x01\x00\x80\x00\x00\x1f\
+            \x00\x0a\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08\
+            \x00\x08\x00\x07\x00\x01\x18\x00\xc0\x00\x02\
+            \x00\x0c\x00\x04\x00\x00\x00\x00";
+        assert_eq!(&bytes[12..], correct_opt);
+
+        let decoded = Message::from_bytes(&mut Cursor::new(&bytes[..]))?;
+        assert_eq!(decoded, message);
+        // the extended bits of 1 with a header RCODE of 0 make 16
+        assert_eq!(decoded.rcode(), Rcode::BADVERS);
+
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_message() -> Result<()> {
+        let name = DomainName::new("www.example.com");
+        let message = Message {
+            header: Header {
+                id: 0xbeef,
+                flags: Flags::new().with_response(true),
+                num_questions: 1,
+                num_answers: 2,
+                num_authorities: 0,
+                num_additionals: 1,
+            },
+            questions: vec![Question {
+                qname: name.clone(),
+                qtype: QType::A,
+                qclass: QClass::IN,
+            }],
+            answers: vec![
+                Record {
+                    name: name.clone(),
+                    qtype: QType::CNAME,
+                    class: QClass::IN,
+                    time_to_live: 300,
+                    rdata: RData::CNAME(DomainName::new("web.example.com")),
+                },
+                Record {
+                    name: DomainName::new("web.example.com"),
+                    qtype: QType::A,
+                    class: QClass::IN,
+                    time_to_live: 300,
+                    rdata: RData::A(std::net::Ipv4Addr::new(93, 184, 216, 34)),
+                },
+            ],
+            authorities: vec![],
+            additionals: vec![],
+            edns: Some(Edns::default()),
+        };
+        let bytes = message.to_bytes()?;
+
+        // see tests/allocations.rs for reading the message without allocating
+        let view = MessageRef::from_bytes(&bytes)?;
+        assert!(view.questions().all(|question| question.qname == name));
+        let answer_names = view
+            .records(MsgSection::Answers)
+            .filter(|record| record.name == name)
+            .count();
+        assert_eq!(answer_names, 1);
+        assert_eq!(view.header().id, 0xbeef);
+        let question = view.questions().next().unwrap();
+        assert_eq!(question.to_question(), message.questions[0]);
+        let answers = view
+            .records(MsgSection::Answers)
+            .map(|record| record.to_record())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(answers, message.answers);
+        assert_eq!(
+            view.records(MsgSection::Additionals).next().unwrap().qtype,
+            QType::OPT
+        );
+        assert_eq!(view.to_message()?, message);
+
+        // a record running past the end of the message is caught up front
+        assert!(MessageRef::from_bytes(&bytes[..bytes.len() - 12]).is_err());
+        Ok(())
+    }
+}
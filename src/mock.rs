@@ -0,0 +1,383 @@
+//! An in-memory [`Transport`] answering queries from scripted name servers instead of the network.
+//!
+//! Each server is given the zones it is authoritative for, and answers from their records
+//! as described in [RFC 1034 section 4.3.2](https://datatracker.ietf.org/doc/html/rfc1034#section-4.3.2):
+//! with the records asked for, the aliases leading to them, a referral to the name servers
+//! of a zone cut beneath, or a negative answer. A hierarchy of such servers, from the root
+//! down to the zone of a name, resolves it without leaving the process.
+//!
+//! Servers may instead be scripted to respond however a test needs, or not at all.
+//! Servers are told apart by address alone, whatever port queries are sent to.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use crate::{
+    dname::DomainName,
+    header::{Flags, Header},
+    message::{self, Message},
+    qtype::QType,
+    question::Question,
+    rcode::Rcode,
+    record::{Edns, Record},
+    transport::{self, Discarded, Options, Transport},
+};
+
+type Script = Box<dyn Fn(&Message) -> Option<Message> + Send + Sync>;
+
+/// How many aliases a mock server follows within its zone before leaving the rest to the resolver
+const MAX_ALIASES: usize = 16;
+
+/// Answers queries from memory, see the [module documentation](self)
+#[derive(Debug, Default)]
+pub struct Mock {
+    servers: Mutex<HashMap<IpAddr, Server>>,
+    queries: Mutex<Vec<(IpAddr, Question)>>,
+    discarded: Discarded,
+}
+
+/// How a mock server responds
+enum Server {
+    /// from the records of the zones it is authoritative for
+    Zones(Vec<Zone>),
+    /// with whatever the script returns, nothing if [`None`]
+    Script(Script),
+}
+
+impl std::fmt::Debug for Server {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Server::Zones(zones) => f.debug_tuple("Zones").field(zones).finish(),
+            Server::Script(_) => f.write_str("Script"),
+        }
+    }
+}
+
+/// The records a mock server holds for a zone
+#[derive(Debug)]
+struct Zone {
+    origin: DomainName,
+    records: Vec<Record>,
+}
+
+impl Mock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `server` authoritative for the zone at `origin`, holding `records`.
+    ///
+    /// NS records owned by a name beneath `origin` delegate that name to another zone;
+    /// the addresses of its name servers among `records` are sent along as glue.
+    /// A server may serve several zones, answering from the closest one enclosing the question.
+    pub fn serve_zone(&self, server: IpAddr, origin: DomainName, records: Vec<Record>) {
+        let zone = Zone { origin, records };
+        let mut servers = self.servers();
+        match servers.get_mut(&server) {
+            Some(Server::Zones(zones)) => zones.push(zone),
+            _ => {
+                servers.insert(server, Server::Zones(vec![zone]));
+            }
+        }
+    }
+
+    /// Has `server` respond to each query with what `script` returns, or not at all if it returns [`None`].
+    ///
+    /// [`Mock::reply_to`] gives a response to fill in. Responses that do not match the query,
+    /// e.g. by ID, are discarded like those arriving over the network.
+    pub fn script(
+        &self,
+        server: IpAddr,
+        script: impl Fn(&Message) -> Option<Message> + Send + Sync + 'static,
+    ) {
+        self.servers()
+            .insert(server, Server::Script(Box::new(script)));
+    }
+
+    /// An empty response to `query`, echoing its ID, question and EDNS
+    pub fn reply_to(query: &Message) -> Message {
+        Message {
+            header: Header {
+                id: query.header.id,
+                flags: Flags::new()
+                    .with_response(true)
+                    .with_recursion_desired(query.header.flags.recursion_desired()),
+                num_questions: 0,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 0,
+            },
+            questions: query.questions.clone(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: query.edns().map(|_| Edns::default()),
+        }
+    }
+
+    /// Every question received, in order, along with the server it was sent to
+    pub fn queries(&self) -> Vec<(IpAddr, Question)> {
+        self.queries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Counts the scripted responses discarded for not being the response to a query
+    pub fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+
+    fn servers(&self) -> MutexGuard<'_, HashMap<IpAddr, Server>> {
+        self.servers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The response of a server authoritative for `zones` to `query`
+    fn answer(zones: &[Zone], query: &Message) -> Message {
+        let mut resp = Self::reply_to(query);
+        let Some(question) = query.questions.first() else {
+            resp.header.flags = resp.header.flags.with_rcode(Rcode::FORMERR);
+            return resp;
+        };
+
+        let closest = zones
+            .iter()
+            .filter(|zone| question.qname.is_subdomain_of(&zone.origin))
+            .max_by_key(|zone| zone.origin.num_labels());
+        match closest {
+            Some(zone) => zone.answer(question, &mut resp),
+            None => resp.header.flags = resp.header.flags.with_rcode(Rcode::REFUSED),
+        }
+        resp
+    }
+}
+
+impl Transport for Mock {
+    fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        self.queries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(query.questions.iter().map(|q| (server.ip(), q.clone())));
+
+        let resp = match self.servers().get(&server.ip()) {
+            Some(Server::Zones(zones)) => Some(Self::answer(zones, query)),
+            Some(Server::Script(script)) => script(query),
+            None => None,
+        };
+
+        // responses go through the wire format and are matched like those from the network,
+        // a server that does not respond times out right away
+        let Some(resp) = resp else {
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        };
+        transport::match_response(query, &resp.to_bytes()?, options, &self.discarded)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::TimedOut).into())
+    }
+
+    fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+}
+
+impl Zone {
+    /// Fills in `resp` to `question`, following aliases within the zone, see
+    /// [RFC 6672 section 3.2](https://datatracker.ietf.org/doc/html/rfc6672#section-3.2) for DNAME
+    fn answer(&self, question: &Question, resp: &mut Message) {
+        let mut name = question.qname.clone();
+        loop {
+            if let Some(cut) = self.zone_cut(&name) {
+                resp.authorities = self.rrset(cut, QType::NS).cloned().collect();
+                resp.additionals = self.glue(&resp.authorities);
+                return;
+            }
+            resp.header.flags = resp.header.flags.with_authoritative(true);
+
+            let dname = self
+                .records
+                .iter()
+                .filter(|rr| rr.qtype == QType::DNAME)
+                .find(|rr| {
+                    name.num_labels() > rr.name.num_labels() && name.is_subdomain_of(&rr.name)
+                });
+            let records: Vec<Record> = self.rrset(&name, question.qtype).cloned().collect();
+            let cname = self.rrset(&name, QType::CNAME).next();
+
+            let alias = if let Some(dname) = dname {
+                // names beneath a DNAME are answered with it and the CNAME it stands for
+                let Some(cname) = dname.synthesize_cname(&name) else {
+                    resp.header.flags = resp.header.flags.with_rcode(Rcode::YXDOMAIN);
+                    return;
+                };
+                resp.answers.push(dname.clone());
+                cname
+            } else if !records.is_empty() {
+                resp.answers.extend(records);
+                return;
+            } else if let Some(cname) = cname.filter(|_| question.qtype != QType::CNAME) {
+                cname.clone()
+            } else {
+                break;
+            };
+
+            let target = alias.rdata.as_name().cloned();
+            resp.answers.push(alias);
+            let Some(target) = target else {
+                return;
+            };
+            // a target elsewhere is left for the resolver to chase, as is a loop or a long chain
+            let seen = resp.answers.iter().any(|rr| rr.name == target);
+            if seen || resp.answers.len() >= MAX_ALIASES || !target.is_subdomain_of(&self.origin) {
+                return;
+            }
+            name = target;
+        }
+
+        // a name owning no records still exists if names beneath it do
+        if !self.records.iter().any(|rr| rr.name.is_subdomain_of(&name)) {
+            resp.header.flags = resp.header.flags.with_rcode(Rcode::NXDOMAIN);
+        }
+        resp.authorities
+            .extend(self.rrset(&self.origin, QType::SOA).cloned());
+    }
+
+    /// The highest zone cut beneath the origin at or above `name`
+    fn zone_cut(&self, name: &DomainName) -> Option<&DomainName> {
+        self.records
+            .iter()
+            .filter(|rr| rr.qtype == QType::NS && rr.name != self.origin)
+            .map(|rr| &rr.name)
+            .filter(|owner| name.is_subdomain_of(owner))
+            .min_by_key(|owner| owner.num_labels())
+    }
+
+    fn rrset<'a>(
+        &'a self,
+        name: &'a DomainName,
+        qtype: QType,
+    ) -> impl Iterator<Item = &'a Record> + 'a {
+        self.records
+            .iter()
+            .filter(move |rr| rr.name == *name && rr.qtype == qtype)
+    }
+
+    /// The addresses the zone holds for the name servers in `delegation`
+    fn glue(&self, delegation: &[Record]) -> Vec<Record> {
+        self.records
+            .iter()
+            .filter(|rr| matches!(rr.qtype, QType::A | QType::AAAA))
+            .filter(|rr| {
+                delegation
+                    .iter()
+                    .any(|ns| ns.rdata.as_name() == Some(&rr.name))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{rdata::RData, test_util::record};
+
+    fn ask(mock: &Mock, server: IpAddr, name: &str, qtype: QType) -> message::Result<Message> {
+        let query = crate::query_message(DomainName::new(name), qtype, Flags::new(), None);
+        mock.send(&query, SocketAddr::new(server, 53), &Options::default())
+    }
+
+    #[test]
+    fn answers_as_authoritative_server() -> message::Result<()> {
+        let server = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 53));
+        let soa = record(
+            "example.com",
+            3600,
+            RData::SOA {
+                mname: DomainName::new("ns.example.com"),
+                rname: DomainName::new("hostmaster.example.com"),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            },
+        );
+        let www = record(
+            "www.example.com",
+            3600,
+            RData::CNAME(DomainName::new("web.example.com")),
+        );
+        let web = record(
+            "web.example.com",
+            3600,
+            RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        );
+        let delegation = record(
+            "sub.example.com",
+            3600,
+            RData::NS(DomainName::new("ns.sub.example.com")),
+        );
+        let glue = record(
+            "ns.sub.example.com",
+            3600,
+            RData::A(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+        let mock = Mock::new();
+        mock.serve_zone(
+            server,
+            DomainName::new("example.com"),
+            vec![
+                soa.clone(),
+                www.clone(),
+                web.clone(),
+                delegation.clone(),
+                glue.clone(),
+            ],
+        );
+
+        // aliases are followed within the zone
+        let resp = ask(&mock, server, "www.example.com", QType::A)?;
+        assert!(resp.header.flags.is_authoritative());
+        assert_eq!(resp.answers, vec![www, web]);
+
+        // names beneath a zone cut are referred to its name servers
+        let resp = ask(&mock, server, "host.sub.example.com", QType::A)?;
+        assert!(!resp.header.flags.is_authoritative());
+        assert_eq!(resp.authorities, vec![delegation]);
+        assert_eq!(resp.additionals, vec![glue]);
+
+        let resp = ask(&mock, server, "web.example.com", QType::AAAA)?;
+        assert_eq!(resp.rcode(), Rcode::NOERROR);
+        assert_eq!(resp.authorities, vec![soa.clone()]);
+
+        let resp = ask(&mock, server, "nowhere.example.com", QType::A)?;
+        assert_eq!(resp.rcode(), Rcode::NXDOMAIN);
+        assert_eq!(resp.authorities, vec![soa]);
+
+        let resp = ask(&mock, server, "example.org", QType::A)?;
+        assert_eq!(resp.rcode(), Rcode::REFUSED);
+
+        // unknown servers never respond, and scripted responses must match the query
+        let silent = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 54));
+        assert!(ask(&mock, silent, "www.example.com", QType::A).is_err());
+        mock.script(silent, |query| {
+            let mut resp = Mock::reply_to(query);
+            resp.header.id = query.header.id.wrapping_add(1);
+            Some(resp)
+        });
+        assert!(ask(&mock, silent, "www.example.com", QType::A).is_err());
+        assert_eq!(mock.discarded().wrong_id(), 1);
+
+        assert_eq!(mock.queries().len(), 7);
+        Ok(())
+    }
+}
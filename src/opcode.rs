@@ -0,0 +1,42 @@
+/// The possible OPCODE field values of a [`Header`](crate::header::Header), as defined in
+/// [RFC 1035 4.1.1](https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.1) and later RFCs
+///
+/// The kind of query is set by the originator of a query and copied into the response.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::FromPrimitive, num_enum::IntoPrimitive,
+)]
+#[repr(u8)]
+pub enum Opcode {
+    /// a standard query
+    QUERY = 0,
+    /// an inverse query
+    #[deprecated = "Obsoleted by RFC 3425"]
+    IQUERY = 1,
+    /// a server status request
+    STATUS = 2,
+    /// a zone change notification (see RFC 1996)
+    NOTIFY = 4,
+    /// a dynamic update (see RFC 2136)
+    UPDATE = 5,
+    /// DNS stateful operations (see RFC 8490)
+    DSO = 6,
+    /// an opcode reserved for future use
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl std::fmt::Display for Opcode {
+    #[allow(deprecated)]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Opcode::QUERY => f.write_str("QUERY"),
+            Opcode::IQUERY => f.write_str("IQUERY"),
+            Opcode::STATUS => f.write_str("STATUS"),
+            Opcode::NOTIFY => f.write_str("NOTIFY"),
+            Opcode::UPDATE => f.write_str("UPDATE"),
+            Opcode::DSO => f.write_str("DSO"),
+            Opcode::Unknown(code) => write!(f, "OPCODE{code}"),
+        }
+    }
+}
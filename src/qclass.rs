@@ -2,9 +2,13 @@
 ///
 /// We use this enum is place of all CLASS _and_ QCLASS values, for code clarity's sake.
 /// > "every CLASS is a valid QCLASS" -- RFC 1035
+///
+/// Values without a variant of their own are kept as [`QClass::Unknown`], and written as
+/// `CLASS` followed by their decimal value, see
+/// [RFC 3597 section 5](https://datatracker.ietf.org/doc/html/rfc3597#section-5)
 #[allow(clippy::upper_case_acronyms)]
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::TryFromPrimitive, num_enum::IntoPrimitive,
+    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::FromPrimitive, num_enum::IntoPrimitive,
 )]
 #[repr(u16)]
 pub enum QClass {
@@ -19,4 +23,93 @@ pub enum QClass {
     HS = 4,
     /// any class (denoted as "*" in RFC 1035)
     ANY = 255,
+    /// a class this crate has no name for
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+#[allow(deprecated)]
+impl QClass {
+    /// Every class with a variant of its own
+    pub const KNOWN: [QClass; 5] = [QClass::IN, QClass::CS, QClass::CH, QClass::HS, QClass::ANY];
+
+    /// The name of the class in master files, or [`None`] for [`QClass::Unknown`]
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        let mnemonic = match self {
+            QClass::IN => "IN",
+            QClass::CS => "CS",
+            QClass::CH => "CH",
+            QClass::HS => "HS",
+            QClass::ANY => "ANY",
+            QClass::Unknown(_) => return None,
+        };
+        Some(mnemonic)
+    }
+}
+
+impl std::fmt::Display for QClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => f.write_str(mnemonic),
+            None => write!(f, "CLASS{}", u16::from(*self)),
+        }
+    }
+}
+
+impl std::str::FromStr for QClass {
+    type Err = Error;
+
+    /// Parses a class from its mnemonic, or from `CLASS` followed by its decimal value,
+    /// both without regard to case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(qclass) = Self::KNOWN
+            .into_iter()
+            .find(|qclass| qclass.mnemonic().is_some_and(|m| m.eq_ignore_ascii_case(s)))
+        {
+            return Ok(qclass);
+        }
+        s.get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("CLASS"))
+            .and_then(|_| s[5..].parse::<u16>().ok())
+            .map(QClass::from)
+            .ok_or_else(|| Error::Unknown(s.to_string()))
+    }
+}
+
+/// Wraps the errors that may be encountered while parsing a [`QClass`] from text
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The text is neither a known mnemonic nor of the form `CLASS###`
+    #[error("Unknown record class {0:?}")]
+    Unknown(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_classes() {
+        assert_eq!(QClass::from(0x0123), QClass::Unknown(0x0123));
+        assert_eq!(u16::from(QClass::Unknown(0x0123)), 0x0123);
+        assert_eq!(QClass::Unknown(0x0123).mnemonic(), None);
+        assert_eq!(QClass::Unknown(0x0123).to_string(), "CLASS291");
+        assert_eq!(QClass::CH.mnemonic(), Some("CH"));
+        assert_eq!(QClass::IN.to_string(), "IN");
+
+        // every class reads back from what it is written as
+        for qclass in QClass::KNOWN.into_iter().chain([QClass::Unknown(0x0123)]) {
+            assert_eq!(qclass.to_string().parse::<QClass>().unwrap(), qclass);
+        }
+        assert_eq!("in".parse::<QClass>().unwrap(), QClass::IN);
+        assert_eq!(
+            "CLASS291".parse::<QClass>().unwrap(),
+            QClass::Unknown(0x0123)
+        );
+        // known classes parse to their own variant in either form
+        assert_eq!("class1".parse::<QClass>().unwrap(), QClass::IN);
+        for invalid in ["", "CLASS", "CLASS65536", "CLASS-1", "INN"] {
+            assert!(invalid.parse::<QClass>().is_err());
+        }
+    }
 }
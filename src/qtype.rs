@@ -2,9 +2,13 @@
 ///
 /// We use this enum is place of all TYPE _and_ QTYPE values, for code clarity's sake.
 /// > "all TYPEs are valid QTYPEs" -- RFC 1035
+///
+/// Values without a variant of their own are kept as [`QType::Unknown`], and written as
+/// `TYPE` followed by their decimal value, see
+/// [RFC 3597 section 5](https://datatracker.ietf.org/doc/html/rfc3597#section-5)
 #[allow(clippy::upper_case_acronyms)]
 #[derive(
-    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::TryFromPrimitive, num_enum::IntoPrimitive,
+    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::FromPrimitive, num_enum::IntoPrimitive,
 )]
 #[repr(u16)]
 pub enum QType {
@@ -49,6 +53,12 @@ pub enum QType {
     TXT = 16,
     /// an IPv6 host address (see RFC 3596)
     AAAA = 28,
+    /// the location of a service (see RFC 2782)
+    SRV = 33,
+    /// a redirection of a whole subtree of names to another (see RFC 6672)
+    DNAME = 39,
+    /// the EDNS(0) pseudo-record, only found in the additional section (see RFC 6891)
+    OPT = 41,
     // QTYPEs below
     /// A request for a transfer of an entire zone
     AXFR = 252,
@@ -60,4 +70,132 @@ pub enum QType {
     MAILA = 254,
     /// A request for all records (denoted as "*" in RFC 1035)
     ANY = 255,
+    /// certification authorities allowed to issue for a domain (see RFC 8659)
+    CAA = 257,
+    /// a type this crate has no name for, e.g. one assigned after it was written
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+#[allow(deprecated)]
+impl QType {
+    /// Every type with a variant of its own
+    pub const KNOWN: [QType; 25] = [
+        QType::A,
+        QType::NS,
+        QType::MD,
+        QType::MF,
+        QType::CNAME,
+        QType::SOA,
+        QType::MB,
+        QType::MG,
+        QType::MR,
+        QType::NULL,
+        QType::WKS,
+        QType::PTR,
+        QType::HINFO,
+        QType::MINFO,
+        QType::MX,
+        QType::TXT,
+        QType::AAAA,
+        QType::SRV,
+        QType::DNAME,
+        QType::OPT,
+        QType::AXFR,
+        QType::MAILB,
+        QType::MAILA,
+        QType::ANY,
+        QType::CAA,
+    ];
+
+    /// The name of the type in master files, or [`None`] for [`QType::Unknown`]
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        let mnemonic = match self {
+            QType::A => "A",
+            QType::NS => "NS",
+            QType::MD => "MD",
+            QType::MF => "MF",
+            QType::CNAME => "CNAME",
+            QType::SOA => "SOA",
+            QType::MB => "MB",
+            QType::MG => "MG",
+            QType::MR => "MR",
+            QType::NULL => "NULL",
+            QType::WKS => "WKS",
+            QType::PTR => "PTR",
+            QType::HINFO => "HINFO",
+            QType::MINFO => "MINFO",
+            QType::MX => "MX",
+            QType::TXT => "TXT",
+            QType::AAAA => "AAAA",
+            QType::SRV => "SRV",
+            QType::DNAME => "DNAME",
+            QType::OPT => "OPT",
+            QType::AXFR => "AXFR",
+            QType::MAILB => "MAILB",
+            QType::MAILA => "MAILA",
+            QType::ANY => "ANY",
+            QType::CAA => "CAA",
+            QType::Unknown(_) => return None,
+        };
+        Some(mnemonic)
+    }
+}
+
+impl std::fmt::Display for QType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.mnemonic() {
+            Some(mnemonic) => f.write_str(mnemonic),
+            None => write!(f, "TYPE{}", u16::from(*self)),
+        }
+    }
+}
+
+impl std::str::FromStr for QType {
+    type Err = Error;
+
+    /// Parses a type from its mnemonic, or from `TYPE` followed by its decimal value,
+    /// both without regard to case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(qtype) = Self::KNOWN
+            .into_iter()
+            .find(|qtype| qtype.mnemonic().is_some_and(|m| m.eq_ignore_ascii_case(s)))
+        {
+            return Ok(qtype);
+        }
+        s.get(..4)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("TYPE"))
+            .and_then(|_| s[4..].parse::<u16>().ok())
+            .map(QType::from)
+            .ok_or_else(|| Error::Unknown(s.to_string()))
+    }
+}
+
+/// Wraps the errors that may be encountered while parsing a [`QType`] from text
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The text is neither a known mnemonic nor of the form `TYPE###`
+    #[error("Unknown record type {0:?}")]
+    Unknown(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_types() {
+        assert_eq!(QType::from(65), QType::Unknown(65));
+        assert_eq!(u16::from(QType::Unknown(65)), 65);
+        assert_eq!(QType::Unknown(65).to_string(), "TYPE65");
+        assert_eq!(QType::CAA.to_string(), "CAA");
+
+        assert_eq!("aaaa".parse::<QType>().unwrap(), QType::AAAA);
+        assert_eq!("TYPE65".parse::<QType>().unwrap(), QType::Unknown(65));
+        // known types parse to their own variant in either form
+        assert_eq!("type1".parse::<QType>().unwrap(), QType::A);
+        for invalid in ["", "TYPE", "TYPE65536", "HTTPSS"] {
+            assert!(invalid.parse::<QType>().is_err());
+        }
+    }
 }
@@ -38,10 +38,14 @@ use std::io::Cursor;
 
 use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::{dname::DomainName, qclass::QClass, qtype::QType};
+use crate::{
+    dname::{Compressor, DomainName, NameRef},
+    qclass::QClass,
+    qtype::QType,
+};
 
 /// Carries the parameters that define what is being asked
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Question {
     /// a domain name represented as a sequence of labels,
     /// where each label consists of a length octet followed by that number of octets.
@@ -72,11 +76,19 @@ impl Question {
         buf
     }
 
+    /// Appends a [`Question`] to a message being built in `buf`, compressing its name
+    pub fn write_to(&self, buf: &mut Vec<u8>, compressor: &mut Compressor) {
+        self.qname.write_compressed(buf, compressor);
+
+        buf.write_u16::<NetworkEndian>(self.qtype.into()).unwrap();
+        buf.write_u16::<NetworkEndian>(self.qclass.into()).unwrap();
+    }
+
     /// Reads a [`Question`] from a slice of bytes
     pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
         let qname = DomainName::from_bytes(bytes)?;
-        let qtype = QType::try_from(bytes.read_u16::<NetworkEndian>()?)?;
-        let qclass = QClass::try_from(bytes.read_u16::<NetworkEndian>()?)?;
+        let qtype = QType::from(bytes.read_u16::<NetworkEndian>()?);
+        let qclass = QClass::from(bytes.read_u16::<NetworkEndian>()?);
 
         Ok(Self {
             qname,
@@ -86,6 +98,44 @@ impl Question {
     }
 }
 
+/// A [`Question`] read in place from a message, see [`MessageRef`](crate::message::MessageRef)
+#[derive(Debug, Clone, Copy)]
+pub struct QuestionRef<'a> {
+    pub qname: NameRef<'a>,
+    pub qtype: QType,
+    pub qclass: QClass,
+}
+
+impl<'a> QuestionRef<'a> {
+    /// Reads the question at `position` of `message`, which holds the message from its first octet.
+    ///
+    /// Returns the question and the position after it.
+    pub fn read(message: &'a [u8], position: usize) -> Result<(Self, usize)> {
+        let (qname, end) = NameRef::read(message, position)?;
+
+        let mut bytes = Cursor::new(message);
+        bytes.set_position(end as u64);
+        let qtype = QType::from(bytes.read_u16::<NetworkEndian>()?);
+        let qclass = QClass::from(bytes.read_u16::<NetworkEndian>()?);
+
+        let question = Self {
+            qname,
+            qtype,
+            qclass,
+        };
+        Ok((question, bytes.position() as usize))
+    }
+
+    /// Copies the question into an owned [`Question`]
+    pub fn to_question(&self) -> Question {
+        Question {
+            qname: self.qname.to_name(),
+            qtype: self.qtype,
+            qclass: self.qclass,
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, Error>;
 
 /// Wraps the errors that may be encountered during byte decoding of a [`Question`]
@@ -97,12 +147,6 @@ pub enum Error {
     /// Stores an error encountered while parsing the [DomainName]
     #[error(transparent)]
     Name(#[from] crate::dname::Error),
-    /// Stores an error encountered while parsin the [QType]
-    #[error("Failed to convert primitive to QType: {0}")]
-    Type(#[from] num_enum::TryFromPrimitiveError<QType>),
-    /// Stores an error encountered while parsin the [QClass]
-    #[error("Failed to convert primitive to QClass: {0}")]
-    Class(#[from] num_enum::TryFromPrimitiveError<QClass>),
 }
 
 #[cfg(test)]
@@ -0,0 +1,63 @@
+/// The possible RCODE values of a response, as defined in
+/// [RFC 1035 4.1.1](https://datatracker.ietf.org/doc/html/rfc1035#section-4.1.1) and later RFCs
+///
+/// Only the lower 4 bits fit in the [`Header`](crate::header::Header);
+/// the rest are carried by an EDNS(0) OPT record.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, num_enum::FromPrimitive, num_enum::IntoPrimitive,
+)]
+#[repr(u16)]
+pub enum Rcode {
+    /// No error condition
+    NOERROR = 0,
+    /// Format error - The name server was unable to interpret the query.
+    FORMERR = 1,
+    /// Server failure - The name server was unable to process this query due to a problem with the name server.
+    SERVFAIL = 2,
+    /// Name Error - the domain name referenced in the query does not exist.
+    ///
+    /// Meaningful only for responses from an authoritative name server.
+    NXDOMAIN = 3,
+    /// Not Implemented - The name server does not support the requested kind of query.
+    NOTIMP = 4,
+    /// Refused - The name server refuses to perform the specified operation for policy reasons.
+    REFUSED = 5,
+    /// a name exists when it should not (see RFC 2136)
+    YXDOMAIN = 6,
+    /// an RR set exists when it should not (see RFC 2136)
+    YXRRSET = 7,
+    /// an RR set that should exist does not (see RFC 2136)
+    NXRRSET = 8,
+    /// the server is not authoritative for the zone named in the Zone Section (see RFC 2136)
+    NOTAUTH = 9,
+    /// a name used in the Prerequisite or Update Section is not within the zone (see RFC 2136)
+    NOTZONE = 10,
+    /// the EDNS version of the query is not implemented by the responder (see RFC 6891)
+    ///
+    /// Only representable with the extended RCODE bits of an OPT record.
+    BADVERS = 16,
+    /// a response code reserved for future use
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+impl std::fmt::Display for Rcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rcode::NOERROR => f.write_str("NOERROR"),
+            Rcode::FORMERR => f.write_str("FORMERR"),
+            Rcode::SERVFAIL => f.write_str("SERVFAIL"),
+            Rcode::NXDOMAIN => f.write_str("NXDOMAIN"),
+            Rcode::NOTIMP => f.write_str("NOTIMP"),
+            Rcode::REFUSED => f.write_str("REFUSED"),
+            Rcode::YXDOMAIN => f.write_str("YXDOMAIN"),
+            Rcode::YXRRSET => f.write_str("YXRRSET"),
+            Rcode::NXRRSET => f.write_str("NXRRSET"),
+            Rcode::NOTAUTH => f.write_str("NOTAUTH"),
+            Rcode::NOTZONE => f.write_str("NOTZONE"),
+            Rcode::BADVERS => f.write_str("BADVERS"),
+            Rcode::Unknown(code) => write!(f, "RCODE{code}"),
+        }
+    }
+}
@@ -0,0 +1,558 @@
+//! The RDATA field of a resource record describes the resource.
+//!
+//! Its format varies according to the TYPE and CLASS of the record,
+//! e.g. an A record in the IN class carries a 4 octet ARPA Internet address,
+//! while an MX record carries a preference value followed by a domain name.
+//!
+//! See more in [RFC 1035 section 3.3](https://datatracker.ietf.org/doc/html/rfc1035#section-3.3),
+//! [RFC 3596](https://datatracker.ietf.org/doc/html/rfc3596) (AAAA),
+//! [RFC 2782](https://datatracker.ietf.org/doc/html/rfc2782) (SRV),
+//! [RFC 6672](https://datatracker.ietf.org/doc/html/rfc6672) (DNAME)
+//! and [RFC 8659](https://datatracker.ietf.org/doc/html/rfc8659) (CAA)
+
+use std::{
+    io::{Cursor, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    dname::{Compressor, DomainName},
+    qtype::QType,
+};
+
+/// The most octets a character string may hold, as its length is given in a single octet, see
+/// [RFC 1035 section 3.3](https://datatracker.ietf.org/doc/html/rfc1035#section-3.3)
+pub const MAX_CHARACTER_STRING: usize = u8::MAX as usize;
+
+/// The decoded data of a resource record
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    /// a host address
+    A(Ipv4Addr),
+    /// an IPv6 host address
+    AAAA(Ipv6Addr),
+    /// a host which should be authoritative for the specified class and domain
+    NS(DomainName),
+    /// the canonical or primary name for the owner; the owner name is an alias
+    CNAME(DomainName),
+    /// a pointer to some location in the domain name space
+    PTR(DomainName),
+    /// the name replacing the owner at the end of every name beneath it
+    DNAME(DomainName),
+    /// a host willing to act as a mail exchange for the owner name
+    MX {
+        /// the preference given to this RR among others at the same owner, lower values are preferred
+        preference: u16,
+        /// a host willing to act as a mail exchange for the owner name
+        exchange: DomainName,
+    },
+    /// marks the start of a zone of authority
+    SOA {
+        /// the name server that was the original or primary source of data for this zone
+        mname: DomainName,
+        /// the mailbox of the person responsible for this zone
+        rname: DomainName,
+        /// the version number of the original copy of the zone
+        serial: u32,
+        /// time interval (in seconds) before the zone should be refreshed
+        refresh: u32,
+        /// time interval (in seconds) that should elapse before a failed refresh should be retried
+        retry: u32,
+        /// upper limit on the time interval (in seconds) that can elapse before the zone is no longer authoritative
+        expire: u32,
+        /// the TTL (in seconds) of negative responses from this zone (see RFC 2308)
+        minimum: u32,
+    },
+    /// one or more character strings
+    TXT(Vec<Vec<u8>>),
+    /// the location of the server(s) for a specific protocol and domain
+    SRV {
+        /// the priority of this target host, lower values are preferred
+        priority: u16,
+        /// a relative weight for entries with the same priority
+        weight: u16,
+        /// the port on this target host of this service
+        port: u16,
+        /// the domain name of the target host
+        target: DomainName,
+    },
+    /// a certification authority authorized to issue certificates for the owner
+    CAA {
+        /// the issuer critical flag is the most significant bit
+        flags: u8,
+        /// the property identifier, e.g. `issue`
+        tag: Vec<u8>,
+        /// the value associated with the property tag
+        value: Vec<u8>,
+    },
+    /// host information
+    HINFO {
+        /// the CPU type
+        cpu: Vec<u8>,
+        /// the operating system type
+        os: Vec<u8>,
+    },
+    /// data of a type this crate does not decode, kept as-is
+    Unknown {
+        /// the TYPE of the record holding this data
+        rtype: QType,
+        /// the undecoded RDATA octets
+        bytes: Vec<u8>,
+    },
+}
+
+impl RData {
+    /// Reads `length` octets of RDATA for a record of type `rtype`.
+    ///
+    /// `bytes` must hold the whole message, as names in the data may be compressed.
+    pub fn from_bytes(bytes: &mut Cursor<&[u8]>, rtype: QType, length: u16) -> Result<Self> {
+        let end = bytes.position() + u64::from(length);
+
+        let data = match rtype {
+            QType::A => {
+                Self::expect_length(rtype, length, 4)?;
+                let mut octets = [0u8; 4];
+                bytes.read_exact(&mut octets)?;
+                RData::A(Ipv4Addr::from(octets))
+            }
+            QType::AAAA => {
+                Self::expect_length(rtype, length, 16)?;
+                let mut octets = [0u8; 16];
+                bytes.read_exact(&mut octets)?;
+                RData::AAAA(Ipv6Addr::from(octets))
+            }
+            QType::NS => RData::NS(DomainName::from_bytes(bytes)?),
+            QType::CNAME => RData::CNAME(DomainName::from_bytes(bytes)?),
+            QType::PTR => RData::PTR(DomainName::from_bytes(bytes)?),
+            QType::DNAME => RData::DNAME(DomainName::from_bytes(bytes)?),
+            QType::MX => RData::MX {
+                preference: bytes.read_u16::<NetworkEndian>()?,
+                exchange: DomainName::from_bytes(bytes)?,
+            },
+            QType::SOA => RData::SOA {
+                mname: DomainName::from_bytes(bytes)?,
+                rname: DomainName::from_bytes(bytes)?,
+                serial: bytes.read_u32::<NetworkEndian>()?,
+                refresh: bytes.read_u32::<NetworkEndian>()?,
+                retry: bytes.read_u32::<NetworkEndian>()?,
+                expire: bytes.read_u32::<NetworkEndian>()?,
+                minimum: bytes.read_u32::<NetworkEndian>()?,
+            },
+            QType::TXT => {
+                let mut strings = Vec::new();
+                while bytes.position() < end {
+                    strings.push(Self::read_character_string(bytes)?);
+                }
+                RData::TXT(strings)
+            }
+            QType::SRV => RData::SRV {
+                priority: bytes.read_u16::<NetworkEndian>()?,
+                weight: bytes.read_u16::<NetworkEndian>()?,
+                port: bytes.read_u16::<NetworkEndian>()?,
+                target: DomainName::from_bytes(bytes)?,
+            },
+            QType::CAA => {
+                let flags = bytes.read_u8()?;
+                let tag = Self::read_character_string(bytes)?;
+                let value = Self::read_until(bytes, end)?;
+                RData::CAA { flags, tag, value }
+            }
+            QType::HINFO => RData::HINFO {
+                cpu: Self::read_character_string(bytes)?,
+                os: Self::read_character_string(bytes)?,
+            },
+            _ => RData::Unknown {
+                rtype,
+                bytes: Self::read_until(bytes, end)?,
+            },
+        };
+
+        if bytes.position() != end {
+            return Err(Error::Length {
+                rtype,
+                expected: length as usize,
+                actual: (bytes.position() + u64::from(length) - end) as usize,
+            });
+        }
+
+        Ok(data)
+    }
+
+    /// Appends the data to a message being built in `buf`.
+    ///
+    /// Only the names in the types defined by RFC 1035 are compressed,
+    /// as required by [RFC 3597 section 4](https://datatracker.ietf.org/doc/html/rfc3597#section-4).
+    /// TXT data longer than a character string may hold is split across several;
+    /// any other character string that long fails to encode.
+    pub fn write_to(&self, buf: &mut Vec<u8>, compressor: &mut Compressor) -> Result<()> {
+        match self {
+            RData::A(addr) => buf.extend_from_slice(&addr.octets()),
+            RData::AAAA(addr) => buf.extend_from_slice(&addr.octets()),
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) => {
+                name.write_compressed(buf, compressor)
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                buf.write_u16::<NetworkEndian>(*preference).unwrap();
+                exchange.write_compressed(buf, compressor);
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                mname.write_compressed(buf, compressor);
+                rname.write_compressed(buf, compressor);
+                for value in [serial, refresh, retry, expire, minimum] {
+                    buf.write_u32::<NetworkEndian>(*value).unwrap();
+                }
+            }
+            RData::TXT(strings) => {
+                for string in strings {
+                    if string.is_empty() {
+                        buf.push(0);
+                    }
+                    for chunk in string.chunks(MAX_CHARACTER_STRING) {
+                        self.write_character_string(buf, chunk)?;
+                    }
+                }
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                for value in [priority, weight, port] {
+                    buf.write_u16::<NetworkEndian>(*value).unwrap();
+                }
+                buf.extend(target.clone().into_bytes());
+            }
+            RData::DNAME(target) => buf.extend(target.clone().into_bytes()),
+            RData::CAA { flags, tag, value } => {
+                buf.push(*flags);
+                self.write_character_string(buf, tag)?;
+                buf.extend_from_slice(value);
+            }
+            RData::HINFO { cpu, os } => {
+                self.write_character_string(buf, cpu)?;
+                self.write_character_string(buf, os)?;
+            }
+            RData::Unknown { bytes, .. } => buf.extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+
+    /// The TYPE of record this data belongs to
+    pub fn rtype(&self) -> QType {
+        match self {
+            RData::A(_) => QType::A,
+            RData::AAAA(_) => QType::AAAA,
+            RData::NS(_) => QType::NS,
+            RData::CNAME(_) => QType::CNAME,
+            RData::PTR(_) => QType::PTR,
+            RData::DNAME(_) => QType::DNAME,
+            RData::MX { .. } => QType::MX,
+            RData::SOA { .. } => QType::SOA,
+            RData::TXT(_) => QType::TXT,
+            RData::SRV { .. } => QType::SRV,
+            RData::CAA { .. } => QType::CAA,
+            RData::HINFO { .. } => QType::HINFO,
+            RData::Unknown { rtype, .. } => *rtype,
+        }
+    }
+
+    /// Returns the address held by A and AAAA data
+    pub fn as_ip_addr(&self) -> Option<IpAddr> {
+        match self {
+            RData::A(addr) => Some(IpAddr::V4(*addr)),
+            RData::AAAA(addr) => Some(IpAddr::V6(*addr)),
+            _ => None,
+        }
+    }
+
+    /// Returns the domain name held by NS, CNAME, PTR and DNAME data
+    pub fn as_name(&self) -> Option<&DomainName> {
+        match self {
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) | RData::DNAME(name) => {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+// character strings
+impl RData {
+    fn expect_length(rtype: QType, actual: u16, expected: usize) -> Result<()> {
+        if actual as usize == expected {
+            Ok(())
+        } else {
+            Err(Error::Length {
+                rtype,
+                expected,
+                actual: actual as usize,
+            })
+        }
+    }
+
+    /// Reads a single length octet followed by that number of octets
+    fn read_character_string(bytes: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+        let size = bytes.read_u8()?;
+        let mut string = vec![0u8; size as usize];
+        bytes.read_exact(&mut string)?;
+        Ok(string)
+    }
+
+    /// Writes a single length octet followed by `string`, which must fit in [`MAX_CHARACTER_STRING`] octets
+    fn write_character_string(&self, buf: &mut Vec<u8>, string: &[u8]) -> Result<()> {
+        let length = u8::try_from(string.len()).map_err(|_| Error::CharacterStringTooLong {
+            rtype: self.rtype(),
+            length: string.len(),
+        })?;
+        buf.push(length);
+        buf.extend_from_slice(string);
+        Ok(())
+    }
+
+    fn read_until(bytes: &mut Cursor<&[u8]>, end: u64) -> Result<Vec<u8>> {
+        let mut data = vec![0u8; end.saturating_sub(bytes.position()) as usize];
+        bytes.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Writes a character string in quotes, escaping quotes, backslashes and non-printable octets
+    fn fmt_character_string(f: &mut std::fmt::Formatter<'_>, string: &[u8]) -> std::fmt::Result {
+        f.write_str("\"")?;
+        for &byte in string {
+            match byte {
+                b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+                0x20..=0x7E => write!(f, "{}", byte as char)?,
+                _ => write!(f, "\\{byte:03}")?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+/// Writes the data in the presentation format of [RFC 1035 section 5.1](https://datatracker.ietf.org/doc/html/rfc1035#section-5.1)
+impl std::fmt::Display for RData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RData::A(addr) => write!(f, "{addr}"),
+            RData::AAAA(addr) => write!(f, "{addr}"),
+            RData::NS(name) | RData::CNAME(name) | RData::PTR(name) | RData::DNAME(name) => {
+                write!(f, "{name}")
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{preference} {exchange}"),
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{mname} {rname} {serial} {refresh} {retry} {expire} {minimum}"
+            ),
+            RData::TXT(strings) => {
+                for (idx, string) in strings.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(" ")?;
+                    }
+                    Self::fmt_character_string(f, string)?;
+                }
+                Ok(())
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{priority} {weight} {port} {target}"),
+            RData::CAA { flags, tag, value } => {
+                write!(f, "{flags} {} ", String::from_utf8_lossy(tag))?;
+                Self::fmt_character_string(f, value)
+            }
+            RData::HINFO { cpu, os } => {
+                Self::fmt_character_string(f, cpu)?;
+                f.write_str(" ")?;
+                Self::fmt_character_string(f, os)
+            }
+            // the generic encoding of RFC 3597 section 5
+            RData::Unknown { bytes, .. } => {
+                write!(f, "\\# {}", bytes.len())?;
+                if !bytes.is_empty() {
+                    f.write_str(" ")?;
+                    for byte in bytes {
+                        write!(f, "{byte:02x}")?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Wraps the errors that may be encountered during byte encoding and decoding of [`RData`]
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Stores an error encountered while using [std::io] traits and structs
+    #[error("Failed to parse record data: {0}")]
+    Io(#[from] std::io::Error),
+    /// Stores an error encountered while parsing a [DomainName] within the data
+    #[error(transparent)]
+    Name(#[from] crate::dname::Error),
+    /// The RDLENGTH field does not match the size of the data it describes
+    #[error("Expected {expected} octets of {rtype} data, found {actual}")]
+    Length {
+        rtype: QType,
+        expected: usize,
+        actual: usize,
+    },
+    /// A character string other than TXT data is too long to encode
+    #[error(
+        "{rtype} character string of {length} octets exceeds the limit of {MAX_CHARACTER_STRING}"
+    )]
+    CharacterStringTooLong { rtype: QType, length: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes `data` and reads it back
+    fn round_trip(data: &RData) -> Result<RData> {
+        let mut buf = Vec::new();
+        data.write_to(&mut buf, &mut Compressor::default())?;
+
+        let mut bytes = Cursor::new(&buf[..]);
+        RData::from_bytes(&mut bytes, data.rtype(), buf.len() as u16)
+    }
+
+    #[test]
+    fn round_trip_rdata() -> Result<()> {
+        let all_data = [
+            RData::A(Ipv4Addr::new(93, 184, 216, 34)),
+            RData::AAAA("2606:2800:220:1:248:1893:25c8:1946".parse().unwrap()),
+            RData::NS(DomainName::new("a.iana-servers.net")),
+            RData::CNAME(DomainName::new("www.example.com")),
+            RData::PTR(DomainName::new("example.com")),
+            RData::DNAME(DomainName::new("example.net")),
+            RData::MX {
+                preference: 10,
+                exchange: DomainName::new("mail.example.com"),
+            },
+            RData::SOA {
+                mname: DomainName::new("ns.icann.org"),
+                rname: DomainName::new("noc.dns.icann.org"),
+                serial: 2022091303,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 3600,
+            },
+            RData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+            RData::SRV {
+                priority: 0,
+                weight: 5,
+                port: 5060,
+                target: DomainName::new("sip.example.com"),
+            },
+            RData::CAA {
+                flags: 0,
+                tag: b"issue".to_vec(),
+                value: b"letsencrypt.org".to_vec(),
+            },
+            RData::HINFO {
+                cpu: b"INTEL-386".to_vec(),
+                os: b"UNIX".to_vec(),
+            },
+            RData::Unknown {
+                rtype: QType::NULL,
+                bytes: vec![0xde, 0xad, 0xbe, 0xef],
+            },
+        ];
+
+        for data in all_data {
+            assert_eq!(round_trip(&data)?, data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn long_character_strings() -> Result<()> {
+        // TXT data is split across as many character strings as it needs
+        let long = vec![b'x'; 300];
+        let txt = RData::TXT(vec![long.clone()]);
+        assert_eq!(
+            round_trip(&txt)?,
+            RData::TXT(vec![long[..255].to_vec(), long[255..].to_vec()])
+        );
+
+        let hinfo = RData::HINFO {
+            cpu: long,
+            os: b"UNIX".to_vec(),
+        };
+        let mut buf = Vec::new();
+        let result = hinfo.write_to(&mut buf, &mut Compressor::default());
+        assert!(matches!(
+            result,
+            Err(Error::CharacterStringTooLong {
+                rtype: QType::HINFO,
+                length: 300
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_wrong_length() {
+        let mut bytes = Cursor::new(&b"\x5d\xb8\xd8\x22\x00"[..]);
+        let result = RData::from_bytes(&mut bytes, QType::A, 5);
+
+        assert!(matches!(
+            result,
+            Err(Error::Length {
+                expected: 4,
+                actual: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn display_rdata() {
+        let txt = RData::TXT(vec![b"say \"hi\"".to_vec(), b"\x07".to_vec()]);
+        assert_eq!(txt.to_string(), r#""say \"hi\"" "\007""#);
+
+        let mx = RData::MX {
+            preference: 10,
+            exchange: DomainName::new("mail.example.com"),
+        };
+        assert_eq!(mx.to_string(), "10 mail.example.com");
+
+        let unknown = RData::Unknown {
+            rtype: QType::NULL,
+            bytes: vec![0x0a, 0x00],
+        };
+        assert_eq!(unknown.to_string(), r"\# 2 0a00");
+    }
+}
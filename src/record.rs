@@ -1,8 +1,17 @@
-use std::io::{Cursor, Read};
+use std::{
+    io::{Cursor, Read, Seek, SeekFrom},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
-use byteorder::{NetworkEndian, ReadBytesExt};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::{dname::DomainName, qclass::QClass, qtype::QType};
+use crate::{
+    dname::{Compressor, DomainName, NameRef},
+    qclass::QClass,
+    qtype::QType,
+    rcode::Rcode,
+    rdata::RData,
+};
 
 /// A resource record
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,35 +26,23 @@ pub struct Record {
     ///
     /// Zero values are interpreted to mean that the RR can only be used for the transaction in progress, and should not be cached.
     pub time_to_live: u32,
-    /// a variable length string of octets that describes the resource. The format of this information varies according to the TYPE and CLASS of the resource record.
+    /// describes the resource. The format of this information varies according to the TYPE and CLASS of the resource record.
     ///
     /// For example, the if the TYPE is A and the CLASS is IN, the RDATA field is a 4 octet ARPA Internet address.
-    pub rdata: Vec<u8>,
+    pub rdata: RData,
 }
 
 impl Record {
     /// Reads a [`Record`] from a slice of bytes
     pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
         let qname = DomainName::from_bytes(bytes)?;
-        let qtype = QType::try_from(bytes.read_u16::<NetworkEndian>()?)?;
-        let qclass = QClass::try_from(bytes.read_u16::<NetworkEndian>()?)?;
+        let qtype = QType::from(bytes.read_u16::<NetworkEndian>()?);
+        let qclass = QClass::from(bytes.read_u16::<NetworkEndian>()?);
         let ttl = bytes.read_u32::<NetworkEndian>()?;
 
         let data_length = bytes.read_u16::<NetworkEndian>()?;
 
-        let data = match qtype {
-            QType::NS | QType::CNAME => String::from(DomainName::from_bytes(bytes)?).into_bytes(),
-            QType::A => {
-                let mut data = vec![0; data_length as usize];
-                bytes.read_exact(&mut data)?;
-                data[..4].to_vec()
-            }
-            _ => {
-                let mut data = vec![0; data_length as usize];
-                bytes.read_exact(&mut data)?;
-                data
-            }
-        };
+        let data = RData::from_bytes(bytes, qtype, data_length)?;
 
         Ok(Self {
             name: qname,
@@ -55,27 +52,436 @@ impl Record {
             rdata: data,
         })
     }
+
+    /// Appends a [`Record`] to a message being built in `buf`,
+    /// compressing its owner name and any domain name held in its data
+    ///
+    /// Fails if the data cannot be encoded, or is longer than RDLENGTH can tell
+    pub fn write_to(&self, buf: &mut Vec<u8>, compressor: &mut Compressor) -> Result<()> {
+        self.name.write_compressed(buf, compressor);
+
+        buf.write_u16::<NetworkEndian>(self.qtype.into()).unwrap();
+        buf.write_u16::<NetworkEndian>(self.class.into()).unwrap();
+        buf.write_u32::<NetworkEndian>(self.time_to_live).unwrap();
+
+        // RDLENGTH is only known once the data is written
+        let length_pos = buf.len();
+        buf.write_u16::<NetworkEndian>(0).unwrap();
+
+        self.rdata.write_to(buf, compressor)?;
+
+        let length = buf.len() - length_pos - 2;
+        let data_length = u16::try_from(length).map_err(|_| Error::DataTooLong { length })?;
+        buf[length_pos..length_pos + 2].copy_from_slice(&data_length.to_be_bytes());
+        Ok(())
+    }
+
+    /// The CNAME record a DNAME record stands for at `name`, a name beneath its owner, see
+    /// [RFC 6672 section 3.3](https://datatracker.ietf.org/doc/html/rfc6672#section-3.3).
+    ///
+    /// Returns [`None`] if the record is no DNAME, `name` does not lie beneath its owner,
+    /// or the name it redirects `name` to would be too long.
+    pub fn synthesize_cname(&self, name: &DomainName) -> Option<Record> {
+        let RData::DNAME(target) = &self.rdata else {
+            return None;
+        };
+        Some(Record {
+            name: name.clone(),
+            qtype: QType::CNAME,
+            class: self.class,
+            time_to_live: self.time_to_live,
+            rdata: RData::CNAME(name.replace_suffix(&self.name, target)?),
+        })
+    }
 }
 
-// parsing data
-impl Record {
-    pub fn data_as_str(&self) -> &str {
-        std::str::from_utf8(&self.rdata).unwrap()
-    }
-
-    pub fn data_as_ip_addr(&self) -> std::net::IpAddr {
-        match self.qtype {
-            QType::A => std::net::IpAddr::V4(std::net::Ipv4Addr::from(
-                <[u8; 4]>::try_from(&self.rdata[..4]).unwrap(),
-            )),
-            QType::AAAA => std::net::IpAddr::V6(std::net::Ipv6Addr::from(
-                <[u8; 16]>::try_from(&self.rdata[..16]).unwrap(),
-            )),
-            _ => unreachable!(),
+/// A [`Record`] read in place from a message, see [`MessageRef`](crate::message::MessageRef)
+///
+/// Its data is only decoded when asked to, see [`RecordRef::rdata`].
+#[derive(Debug, Clone, Copy)]
+pub struct RecordRef<'a> {
+    /// a domain name to which this resource record pertains.
+    pub name: NameRef<'a>,
+    pub qtype: QType,
+    pub class: QClass,
+    pub time_to_live: u32,
+    message: &'a [u8],
+    /// where the RDATA starts within the message
+    data_start: usize,
+    data_length: u16,
+}
+
+impl<'a> RecordRef<'a> {
+    /// Reads the record at `position` of `message`, which holds the message from its first octet.
+    ///
+    /// Returns the record and the position after it. Only the owner name and the bounds of the
+    /// data are checked, see [`RecordRef::rdata`].
+    pub fn read(message: &'a [u8], position: usize) -> Result<(Self, usize)> {
+        let (name, end) = NameRef::read(message, position)?;
+
+        let mut bytes = Cursor::new(message);
+        bytes.set_position(end as u64);
+        let qtype = QType::from(bytes.read_u16::<NetworkEndian>()?);
+        let class = QClass::from(bytes.read_u16::<NetworkEndian>()?);
+        let time_to_live = bytes.read_u32::<NetworkEndian>()?;
+        let data_length = bytes.read_u16::<NetworkEndian>()?;
+
+        let data_start = bytes.position() as usize;
+        let data_end = data_start + data_length as usize;
+        if data_end > message.len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let record = Self {
+            name,
+            qtype,
+            class,
+            time_to_live,
+            message,
+            data_start,
+            data_length,
+        };
+        Ok((record, data_end))
+    }
+
+    /// The undecoded RDATA octets, whose names may point elsewhere in the message
+    pub fn rdata_bytes(&self) -> &'a [u8] {
+        &self.message[self.data_start..self.data_start + self.data_length as usize]
+    }
+
+    /// Decodes the RDATA of the record
+    pub fn rdata(&self) -> crate::rdata::Result<RData> {
+        let mut bytes = Cursor::new(self.message);
+        bytes.set_position(self.data_start as u64);
+        RData::from_bytes(&mut bytes, self.qtype, self.data_length)
+    }
+
+    /// Copies the record into an owned [`Record`], decoding its data
+    pub fn to_record(&self) -> Result<Record> {
+        Ok(Record {
+            name: self.name.to_name(),
+            qtype: self.qtype,
+            class: self.class,
+            time_to_live: self.time_to_live,
+            rdata: self.rdata()?,
+        })
+    }
+}
+
+/// Writes the record as a master file line of owner, TTL, class, type and data, see
+/// [RFC 1035 section 5.1](https://datatracker.ietf.org/doc/html/rfc1035#section-5.1)
+///
+/// Unknown classes and types use the generic forms of
+/// [RFC 3597 section 5](https://datatracker.ietf.org/doc/html/rfc3597#section-5), e.g. `TYPE65 \# 3 000100`
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.name, self.time_to_live, self.class, self.qtype, self.rdata
+        )
+    }
+}
+
+/// The EDNS(0) OPT pseudo-record, which extends the header of a message.
+///
+/// It reuses the fields of a resource record as follows:
+///
+/// ```text
+/// +------------+--------------+------------------------------+
+/// | Field Name | Field Type   | Description                  |
+/// +------------+--------------+------------------------------+
+/// | NAME       | domain name  | MUST be 0 (root domain)      |
+/// | TYPE       | u_int16_t    | OPT (41)                     |
+/// | CLASS      | u_int16_t    | requestor's UDP payload size |
+/// | TTL        | u_int32_t    | extended RCODE and flags     |
+/// | RDLEN      | u_int16_t    | length of all RDATA          |
+/// | RDATA      | octet stream | {attribute,value} pairs      |
+/// +------------+--------------+------------------------------+
+///
+///             +0 (MSB)                            +1 (LSB)
+///  +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+///  |         EXTENDED-RCODE        |            VERSION            |
+///  +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+///  | DO|                           Z                               |
+///  +---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+---+
+/// ```
+///
+/// See more in [RFC 6891 section 6](https://datatracker.ietf.org/doc/html/rfc6891#section-6)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    /// the number of octets of the largest UDP payload that can be reassembled and delivered in the sender's network stack
+    pub udp_payload_size: u16,
+    /// the upper 8 bits of the 12-bit RCODE, whose lower 4 bits are in the header
+    pub extended_rcode: u8,
+    /// the implementation level of the sender, 0 for EDNS(0)
+    pub version: u8,
+    /// the DNSSEC OK bit, set when the sender can handle DNSSEC records (see RFC 3225)
+    pub dnssec_ok: bool,
+    /// the options carried in the RDATA
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Self {
+            udp_payload_size: Edns::DEFAULT_UDP_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
         }
     }
 }
 
+impl Edns {
+    /// A payload size that avoids IP fragmentation on most paths, as agreed on for DNS Flag Day 2020
+    pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+    /// Payload sizes below this are treated as equal to it
+    pub const MIN_UDP_PAYLOAD_SIZE: u16 = 512;
+    const DNSSEC_OK: u32 = 1 << 15;
+
+    /// Checks whether the next record in `bytes` is an OPT record, without consuming it
+    pub fn is_next(bytes: &mut Cursor<&[u8]>) -> Result<bool> {
+        let start = bytes.position();
+
+        DomainName::from_bytes(bytes)?;
+        let rtype = bytes.read_u16::<NetworkEndian>()?;
+
+        bytes.seek(SeekFrom::Start(start))?;
+        Ok(rtype == u16::from(QType::OPT))
+    }
+
+    /// Reads an OPT [`Edns`] record from a slice of bytes
+    pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
+        if bytes.read_u8()? != DomainName::TERMINATOR {
+            return Err(Error::OptName);
+        }
+        let rtype = bytes.read_u16::<NetworkEndian>()?;
+        debug_assert_eq!(rtype, u16::from(QType::OPT));
+
+        let udp_payload_size = bytes.read_u16::<NetworkEndian>()?;
+        let ttl = bytes.read_u32::<NetworkEndian>()?;
+        let [extended_rcode, version, ..] = ttl.to_be_bytes();
+
+        let data_length = bytes.read_u16::<NetworkEndian>()?;
+        let end = bytes.position() + u64::from(data_length);
+
+        let mut options = Vec::new();
+        while bytes.position() < end {
+            options.push(EdnsOption::from_bytes(bytes)?);
+        }
+        if bytes.position() != end {
+            return Err(Error::OptLength);
+        }
+
+        Ok(Self {
+            udp_payload_size,
+            extended_rcode,
+            version,
+            dnssec_ok: ttl & Edns::DNSSEC_OK != 0,
+            options,
+        })
+    }
+
+    /// Appends an OPT [`Edns`] record to a message being built in `buf`
+    ///
+    /// Fails if its options are longer than RDLENGTH can tell
+    pub fn write_to(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.push(DomainName::TERMINATOR);
+        buf.write_u16::<NetworkEndian>(QType::OPT.into()).unwrap();
+        buf.write_u16::<NetworkEndian>(self.udp_payload_size)
+            .unwrap();
+        buf.extend_from_slice(&[self.extended_rcode, self.version]);
+        let flags = if self.dnssec_ok { Edns::DNSSEC_OK } else { 0 };
+        buf.write_u16::<NetworkEndian>(flags as u16).unwrap();
+
+        let mut data = Vec::new();
+        for option in &self.options {
+            option.write_to(&mut data);
+        }
+        let length = data.len();
+        let data_length = u16::try_from(length).map_err(|_| Error::DataTooLong { length })?;
+        buf.write_u16::<NetworkEndian>(data_length).unwrap();
+        buf.extend(data);
+        Ok(())
+    }
+
+    /// The full 12-bit response code, given the lower 4 bits from the header
+    pub fn rcode(&self, header_rcode: Rcode) -> Rcode {
+        let low = u16::from(header_rcode) & 0b1111;
+        Rcode::from(u16::from(self.extended_rcode) << 4 | low)
+    }
+
+    /// The payload size a response to this record's sender may use
+    pub fn max_payload_size(&self) -> u16 {
+        self.udp_payload_size.max(Edns::MIN_UDP_PAYLOAD_SIZE)
+    }
+}
+
+/// An option carried in the RDATA of an [`Edns`] OPT record
+///
+/// See the [IANA registry](https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-11) for assigned codes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    /// Name server identifier (see RFC 5001), empty in queries
+    Nsid(Vec<u8>),
+    /// The network the query originated from (see RFC 7871)
+    ClientSubnet {
+        /// the source prefix length
+        source_prefix: u8,
+        /// the scope prefix length, zero in queries
+        scope_prefix: u8,
+        /// the network address, truncated to `source_prefix` bits on the wire
+        address: IpAddr,
+    },
+    /// A client cookie, optionally followed by the server cookie (see RFC 7873)
+    Cookie {
+        /// the 8 octet client cookie
+        client: [u8; 8],
+        /// the 8 to 32 octet server cookie, empty if unknown
+        server: Vec<u8>,
+    },
+    /// The idle timeout of a TCP connection, in units of 100 milliseconds (see RFC 7828)
+    TcpKeepalive(Option<u16>),
+    /// A number of zero octets used to pad a message (see RFC 7830)
+    Padding(u16),
+    /// Additional information about the cause of an error (see RFC 8914)
+    ExtendedError {
+        /// the reason for the error
+        info_code: u16,
+        /// UTF-8 text which may help in debugging
+        extra_text: Vec<u8>,
+    },
+    /// An option this crate does not decode, kept as-is
+    Unknown {
+        /// the OPTION-CODE
+        code: u16,
+        /// the OPTION-DATA
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    pub const NSID: u16 = 3;
+    pub const CLIENT_SUBNET: u16 = 8;
+    pub const COOKIE: u16 = 10;
+    pub const TCP_KEEPALIVE: u16 = 11;
+    pub const PADDING: u16 = 12;
+    pub const EXTENDED_ERROR: u16 = 15;
+
+    /// The OPTION-CODE identifying this option
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => EdnsOption::NSID,
+            EdnsOption::ClientSubnet { .. } => EdnsOption::CLIENT_SUBNET,
+            EdnsOption::Cookie { .. } => EdnsOption::COOKIE,
+            EdnsOption::TcpKeepalive(_) => EdnsOption::TCP_KEEPALIVE,
+            EdnsOption::Padding(_) => EdnsOption::PADDING,
+            EdnsOption::ExtendedError { .. } => EdnsOption::EXTENDED_ERROR,
+            EdnsOption::Unknown { code, .. } => *code,
+        }
+    }
+
+    /// Reads an [`EdnsOption`] from a slice of bytes
+    pub fn from_bytes(bytes: &mut Cursor<&[u8]>) -> Result<Self> {
+        let code = bytes.read_u16::<NetworkEndian>()?;
+        let length = bytes.read_u16::<NetworkEndian>()?;
+        let mut data = vec![0u8; length as usize];
+        bytes.read_exact(&mut data)?;
+
+        let malformed = || Error::OptOption { code, length };
+
+        let option = match code {
+            EdnsOption::NSID => EdnsOption::Nsid(data),
+            EdnsOption::CLIENT_SUBNET => {
+                let [family_high, family_low, source_prefix, scope_prefix, address @ ..] =
+                    &data[..]
+                else {
+                    return Err(malformed());
+                };
+                let address = match u16::from_be_bytes([*family_high, *family_low]) {
+                    1 if address.len() <= 4 => {
+                        let mut octets = [0u8; 4];
+                        octets[..address.len()].copy_from_slice(address);
+                        IpAddr::V4(Ipv4Addr::from(octets))
+                    }
+                    2 if address.len() <= 16 => {
+                        let mut octets = [0u8; 16];
+                        octets[..address.len()].copy_from_slice(address);
+                        IpAddr::V6(Ipv6Addr::from(octets))
+                    }
+                    _ => return Err(malformed()),
+                };
+                EdnsOption::ClientSubnet {
+                    source_prefix: *source_prefix,
+                    scope_prefix: *scope_prefix,
+                    address,
+                }
+            }
+            EdnsOption::COOKIE if data.len() == 8 || (16..=40).contains(&data.len()) => {
+                let (client, server) = data.split_at(8);
+                EdnsOption::Cookie {
+                    client: client.try_into().unwrap(),
+                    server: server.to_vec(),
+                }
+            }
+            EdnsOption::TCP_KEEPALIVE => match data[..] {
+                [] => EdnsOption::TcpKeepalive(None),
+                [high, low] => EdnsOption::TcpKeepalive(Some(u16::from_be_bytes([high, low]))),
+                _ => return Err(malformed()),
+            },
+            EdnsOption::PADDING => EdnsOption::Padding(length),
+            EdnsOption::EXTENDED_ERROR if data.len() >= 2 => EdnsOption::ExtendedError {
+                info_code: u16::from_be_bytes([data[0], data[1]]),
+                extra_text: data[2..].to_vec(),
+            },
+            EdnsOption::COOKIE | EdnsOption::EXTENDED_ERROR => return Err(malformed()),
+            _ => EdnsOption::Unknown { code, data },
+        };
+
+        Ok(option)
+    }
+
+    /// Appends an [`EdnsOption`] to the RDATA of an OPT record being built in `buf`
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
+        let data = match self {
+            EdnsOption::Nsid(data) | EdnsOption::Unknown { data, .. } => data.clone(),
+            EdnsOption::ClientSubnet {
+                source_prefix,
+                scope_prefix,
+                address,
+            } => {
+                let (family, octets) = match address {
+                    IpAddr::V4(addr) => (1u16, addr.octets().to_vec()),
+                    IpAddr::V6(addr) => (2u16, addr.octets().to_vec()),
+                };
+                // only the octets covered by the source prefix are sent
+                let significant = (*source_prefix as usize).div_ceil(8).min(octets.len());
+
+                let mut data = family.to_be_bytes().to_vec();
+                data.extend_from_slice(&[*source_prefix, *scope_prefix]);
+                data.extend_from_slice(&octets[..significant]);
+                data
+            }
+            EdnsOption::Cookie { client, server } => [&client[..], server].concat(),
+            EdnsOption::TcpKeepalive(timeout) => timeout
+                .map(|t| t.to_be_bytes().to_vec())
+                .unwrap_or_default(),
+            EdnsOption::Padding(length) => vec![0u8; *length as usize],
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => [&info_code.to_be_bytes()[..], extra_text].concat(),
+        };
+
+        // an option too long for its length is too long for the OPT record, which fails to be written
+        buf.write_u16::<NetworkEndian>(self.code()).unwrap();
+        buf.write_u16::<NetworkEndian>(data.len() as u16).unwrap();
+        buf.extend(data);
+    }
+}
+
 type Result<T> = std::result::Result<T, Error>;
 
 /// Wraps the errors that may be encountered during byte decoding of a [`Record`]
@@ -87,12 +493,21 @@ pub enum Error {
     /// Stores an error encountered while parsing the [DomainName]
     #[error(transparent)]
     Name(#[from] crate::dname::Error),
-    /// Stores an error encountered while parsin the [QType]
-    #[error("Failed to convert primitive to QType: {0}")]
-    Type(#[from] num_enum::TryFromPrimitiveError<QType>),
-    /// Stores an error encountered while parsin the [QClass]
-    #[error("Failed to convert primitive to QClass: {0}")]
-    Class(#[from] num_enum::TryFromPrimitiveError<QClass>),
+    /// Stores an error encountered while parsing the [RData]
+    #[error(transparent)]
+    Data(#[from] crate::rdata::Error),
+    /// An OPT record must be owned by the root domain
+    #[error("OPT record is not owned by the root domain")]
+    OptName,
+    /// The options of an OPT record overran its RDLENGTH
+    #[error("OPT record options do not match its data length")]
+    OptLength,
+    /// An option within an OPT record is malformed
+    #[error("Malformed EDNS option {code} of length {length}")]
+    OptOption { code: u16, length: u16 },
+    /// The data of a record to encode is longer than RDLENGTH can tell
+    #[error("Record data of {length} octets exceeds the maximum of 65535")]
+    DataTooLong { length: usize },
 }
 
 #[cfg(test)]
@@ -107,7 +522,7 @@ mod tests {
             qtype: QType::A,
             class: QClass::IN,
             time_to_live: 21147,
-            rdata: b"]\xb8\xd8\"".to_vec(),
+            rdata: RData::A(std::net::Ipv4Addr::new(93, 184, 216, 34)),
         };
 
         let mut rec_bytes_reader = Cursor::new(&record_bytes[..]);
@@ -120,4 +535,40 @@ mod tests {
         assert_eq!(result_record, correct_record);
         Ok(())
     }
+
+    #[test]
+    fn oversized_data_rejected() {
+        let record = Record {
+            name: DomainName::new("example.com"),
+            qtype: QType::TXT,
+            class: QClass::IN,
+            time_to_live: 3600,
+            // 300 strings of 256 octets each, length prefix included
+            rdata: RData::TXT(vec![vec![b'x'; 255]; 300]),
+        };
+
+        let err = record
+            .write_to(&mut Vec::new(), &mut Compressor::default())
+            .unwrap_err();
+        assert!(matches!(err, Error::DataTooLong { length: 76800 }), "{err}");
+    }
+
+    #[test]
+    fn unknown_type_and_class() -> Result<()> {
+        // an HTTPS record (type 65) in class 0x0123
+        let bytes = b"\x07example\x03com\x00\x00\x41\x01\x23\x00\x00\x0e\x10\x00\x03\x00\x01\x00";
+        let record = Record::from_bytes(&mut Cursor::new(&bytes[..]))?;
+
+        assert_eq!(record.qtype, QType::Unknown(65));
+        assert_eq!(record.class, QClass::Unknown(0x0123));
+        assert_eq!(
+            record.to_string(),
+            r"example.com 3600 CLASS291 TYPE65 \# 3 000100"
+        );
+
+        let mut buf = Vec::new();
+        record.write_to(&mut buf, &mut Compressor::default())?;
+        assert_eq!(buf, bytes);
+        Ok(())
+    }
 }
@@ -0,0 +1,1887 @@
+//! Iterative resolution of domain names, starting from the root name servers.
+//!
+//! Each query is sent without recursion desired; referrals are followed
+//! from the root down to a server that answers authoritatively, as described in
+//! [RFC 1034 section 5.3.3](https://datatracker.ietf.org/doc/html/rfc1034#section-5.3.3).
+//!
+//! Resolution is written once, as asynchronous code that awaits each query.
+//! [`Resolver::resolve`] sends queries over the resolver's [`Transport`], which blocks, and is driven
+//! to completion on the calling thread, parked should it await anything else. With the `tokio` feature,
+//! [`Resolver::resolve_async`] sends them over the same transport without blocking instead.
+
+use std::{
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use crate::{
+    cache::{Cache, Cached},
+    dname::{self, DomainName},
+    header::Flags,
+    message::{self, Message, MsgSection},
+    qclass::QClass,
+    qtype::QType,
+    rcode::Rcode,
+    record::{Edns, Record},
+    transport::{self, Discarded, Multiplexer, Protocol, Tcp, Transport, Udp, DNS_PORT},
+};
+
+/// The addresses of the thirteen root name servers, a through m, IPv4 first.
+///
+/// See more at [IANA](https://www.iana.org/domains/root/servers)
+pub const ROOT_HINTS: [IpAddr; 26] = [
+    IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4)),
+    IpAddr::V4(Ipv4Addr::new(170, 247, 170, 2)),
+    IpAddr::V4(Ipv4Addr::new(192, 33, 4, 12)),
+    IpAddr::V4(Ipv4Addr::new(199, 7, 91, 13)),
+    IpAddr::V4(Ipv4Addr::new(192, 203, 230, 10)),
+    IpAddr::V4(Ipv4Addr::new(192, 5, 5, 241)),
+    IpAddr::V4(Ipv4Addr::new(192, 112, 36, 4)),
+    IpAddr::V4(Ipv4Addr::new(198, 97, 190, 53)),
+    IpAddr::V4(Ipv4Addr::new(192, 36, 148, 17)),
+    IpAddr::V4(Ipv4Addr::new(192, 58, 128, 30)),
+    IpAddr::V4(Ipv4Addr::new(193, 0, 14, 129)),
+    IpAddr::V4(Ipv4Addr::new(199, 7, 83, 42)),
+    IpAddr::V4(Ipv4Addr::new(202, 12, 27, 33)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30)),
+    IpAddr::V6(Ipv6Addr::new(0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35)),
+];
+
+/// Which address families name servers are contacted over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Only contact name servers over IPv4
+    Ipv4Only,
+    /// Only contact name servers over IPv6
+    Ipv6Only,
+    /// Contact name servers over IPv4 when they have an IPv4 address, otherwise over IPv6
+    #[default]
+    Ipv4First,
+    /// Contact name servers over IPv6 when they have an IPv6 address, otherwise over IPv4
+    Ipv6First,
+}
+
+impl IpPreference {
+    /// Whether name servers may be contacted at `addr`
+    pub fn allows(self, addr: &IpAddr) -> bool {
+        match self {
+            IpPreference::Ipv4Only => addr.is_ipv4(),
+            IpPreference::Ipv6Only => addr.is_ipv6(),
+            IpPreference::Ipv4First | IpPreference::Ipv6First => true,
+        }
+    }
+
+    /// The types of address records to look up for a name server, in order of preference
+    pub fn record_types(self) -> &'static [QType] {
+        match self {
+            IpPreference::Ipv4Only => &[QType::A],
+            IpPreference::Ipv6Only => &[QType::AAAA],
+            IpPreference::Ipv4First => &[QType::A, QType::AAAA],
+            IpPreference::Ipv6First => &[QType::AAAA, QType::A],
+        }
+    }
+
+    /// Orders `addrs` by preference, dropping addresses of a family that may not be used
+    pub fn sort(self, addrs: impl IntoIterator<Item = IpAddr>) -> Vec<IpAddr> {
+        let mut addrs: Vec<IpAddr> = addrs.into_iter().filter(|ip| self.allows(ip)).collect();
+        match self {
+            IpPreference::Ipv4First => addrs.sort_by_key(IpAddr::is_ipv6),
+            IpPreference::Ipv6First => addrs.sort_by_key(IpAddr::is_ipv4),
+            IpPreference::Ipv4Only | IpPreference::Ipv6Only => {}
+        }
+        addrs
+    }
+}
+
+/// Settings for a [`Resolver`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolverConfig {
+    /// The servers resolution starts from when nothing closer to the name is cached
+    pub root_hints: Vec<IpAddr>,
+    /// The port name servers are queried on
+    pub port: u16,
+    /// How long to wait for the first response from each name server
+    pub timeout: Duration,
+    /// How many more rounds of queries are sent after every name server of a zone failed to answer,
+    /// each round waiting twice as long as the last
+    pub retries: usize,
+    /// How many names (name servers and aliases) may be resolved on the way to an answer
+    pub max_depth: usize,
+    /// How many aliases (CNAME records, including those synthesized from DNAME records)
+    /// may be followed on the way to an answer
+    pub max_cname_chain: usize,
+    /// How many referrals may be followed while resolving a single name
+    pub max_referrals: usize,
+    /// Which address families name servers are contacted over
+    pub ip_preference: IpPreference,
+    /// How queries are sent to name servers
+    pub protocol: Protocol,
+    /// Whether to randomize the case of the letters in each query's name ("0x20"),
+    /// only accepting responses that echo it exactly.
+    ///
+    /// Makes responses harder to spoof, but fails against servers that do not preserve case.
+    pub randomize_case: bool,
+    /// The most RRsets and negative answers cached at once, zero disables caching
+    pub cache_size: usize,
+    /// How many long-lived sockets blocking queries over UDP share, see [`Multiplexer`].
+    ///
+    /// Identical queries in flight at once are then only sent once. Zero binds a fresh socket
+    /// to a random port for every query instead.
+    pub udp_sockets: usize,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            root_hints: ROOT_HINTS.to_vec(),
+            port: DNS_PORT,
+            timeout: Duration::from_secs(2),
+            retries: 2,
+            max_depth: 16,
+            max_cname_chain: 8,
+            max_referrals: 32,
+            ip_preference: IpPreference::default(),
+            protocol: Protocol::default(),
+            randomize_case: false,
+            cache_size: 10_000,
+            udp_sockets: 0,
+        }
+    }
+}
+
+/// The answer to a resolved name and type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    /// The name that was resolved
+    pub name: DomainName,
+    /// The type of records that was resolved
+    pub qtype: QType,
+    /// Every record of the requested type, owned by the canonical name
+    pub records: Vec<Record>,
+    /// The CNAME records followed from `name` to the canonical name, in order,
+    /// including those synthesized from `dnames`
+    pub cname_chain: Vec<Record>,
+    /// The DNAME records that redirected names on the way to the canonical name, in order
+    pub dnames: Vec<Record>,
+    /// The server that gave the answer, or [`None`] if it came from the cache
+    pub server: Option<IpAddr>,
+    /// Whether the answering server marked the answer as authenticated (the AD bit)
+    pub authenticated: bool,
+    /// What happened while resolving the name
+    pub trace: Trace,
+}
+
+impl Lookup {
+    /// The name owning the records, i.e. the target of the last alias followed
+    pub fn canonical_name(&self) -> &DomainName {
+        self.records
+            .first()
+            .map(|rr| &rr.name)
+            .unwrap_or(&self.name)
+    }
+
+    /// The lowest TTL among the records and the aliases followed, i.e. how long the whole answer may be kept
+    pub fn min_ttl(&self) -> Option<u32> {
+        self.records
+            .iter()
+            .chain(&self.cname_chain)
+            .chain(&self.dnames)
+            .map(|rr| rr.time_to_live)
+            .min()
+    }
+
+    /// The addresses held by A and AAAA records
+    pub fn ip_addrs(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.records.iter().filter_map(|rr| rr.rdata.as_ip_addr())
+    }
+}
+
+/// What happened while resolving a name, kept for diagnosing failures
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// Every server queried, in order, including those queried for name server addresses
+    pub servers: Vec<IpAddr>,
+    /// The last response received
+    pub last_response: Option<Box<Message>>,
+    /// Every record thrown away for lying outside the bailiwick of the server that sent it
+    pub rejected: Vec<Rejected>,
+}
+
+/// A record sent by a server with no authority over its name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejected {
+    pub record: Record,
+    /// The server that sent the record
+    pub server: IpAddr,
+    /// The zone the server was queried as a name server of
+    pub zone: DomainName,
+}
+
+/// Wraps the reasons resolving a name may fail
+#[derive(Debug, thiserror::Error)]
+pub enum ResolveError {
+    /// The name to resolve is not a valid domain name
+    #[error("Invalid domain name {name:?}: {source}")]
+    InvalidName {
+        name: String,
+        #[source]
+        source: dname::Error,
+        trace: Box<Trace>,
+    },
+    /// The name does not exist
+    #[error("{name} does not exist (NXDOMAIN)")]
+    NxDomain { name: DomainName, trace: Box<Trace> },
+    /// The name exists, but has no records of the requested type
+    #[error("{name} has no {qtype} records")]
+    NoData {
+        name: DomainName,
+        qtype: QType,
+        trace: Box<Trace>,
+    },
+    /// The server was unable to process the query
+    #[error("{server} failed to process the query (SERVFAIL)")]
+    ServFail { server: IpAddr, trace: Box<Trace> },
+    /// The server answered with an error other than SERVFAIL or NXDOMAIN, e.g. REFUSED
+    #[error("{server} responded with {rcode}")]
+    ErrorResponse {
+        server: IpAddr,
+        rcode: Rcode,
+        trace: Box<Trace>,
+    },
+    /// The server did not answer in time
+    #[error("Timed out waiting for {server}")]
+    Timeout { server: IpAddr, trace: Box<Trace> },
+    /// The server neither answered nor referred to other servers, or no referred server could be reached
+    #[error("Lame delegation for {zone}")]
+    LameDelegation { zone: DomainName, trace: Box<Trace> },
+    /// Resolving the name required resolving itself, e.g. through a CNAME or referral loop
+    #[error("Resolution loop detected while resolving {name}")]
+    LoopDetected { name: DomainName, trace: Box<Trace> },
+    /// Too many names had to be resolved on the way to an answer
+    #[error("Exceeded the maximum resolution depth of {depth}")]
+    MaxDepthExceeded { depth: usize, trace: Box<Trace> },
+    /// Too many referrals had to be followed on the way to an answer
+    #[error("Followed more than {max} referrals while resolving {name}")]
+    TooManyReferrals {
+        name: DomainName,
+        max: usize,
+        trace: Box<Trace>,
+    },
+    /// Too many aliases had to be followed on the way to an answer
+    #[error("Exceeded the maximum of {max} aliases while resolving {name}")]
+    CnameChainTooLong {
+        name: DomainName,
+        max: usize,
+        trace: Box<Trace>,
+    },
+    /// The response could not be parsed
+    #[error("Malformed response from {server}: {source}")]
+    MalformedResponse {
+        server: IpAddr,
+        #[source]
+        source: message::Error,
+        trace: Box<Trace>,
+    },
+    /// Stores an error encountered while exchanging messages, other than a timeout
+    #[error("Failed to query {server}: {source}")]
+    Io {
+        server: IpAddr,
+        #[source]
+        source: std::io::Error,
+        trace: Box<Trace>,
+    },
+}
+
+impl ResolveError {
+    /// What happened up to the failure
+    pub fn trace(&self) -> &Trace {
+        match self {
+            ResolveError::InvalidName { trace, .. }
+            | ResolveError::NxDomain { trace, .. }
+            | ResolveError::NoData { trace, .. }
+            | ResolveError::ServFail { trace, .. }
+            | ResolveError::ErrorResponse { trace, .. }
+            | ResolveError::Timeout { trace, .. }
+            | ResolveError::LameDelegation { trace, .. }
+            | ResolveError::LoopDetected { trace, .. }
+            | ResolveError::MaxDepthExceeded { trace, .. }
+            | ResolveError::TooManyReferrals { trace, .. }
+            | ResolveError::CnameChainTooLong { trace, .. }
+            | ResolveError::MalformedResponse { trace, .. }
+            | ResolveError::Io { trace, .. } => trace,
+        }
+    }
+
+    /// The last response received before the failure
+    pub fn last_response(&self) -> Option<&Message> {
+        self.trace().last_response.as_deref()
+    }
+
+    /// Every server queried before the failure, in order
+    pub fn server_chain(&self) -> &[IpAddr] {
+        &self.trace().servers
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ResolveError>;
+
+/// An iterative resolver, along with the answers and referrals it has cached.
+///
+/// Resolvers are independent of each other; each has its own [`ResolverConfig`] and cache.
+/// A single resolver may be shared between threads, and between blocking and asynchronous callers.
+#[derive(Debug)]
+pub struct Resolver {
+    config: ResolverConfig,
+    cache: Mutex<Cache>,
+    transport: Arc<dyn Transport>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new(ResolverConfig::default())
+    }
+}
+
+impl Resolver {
+    /// Creates a [`Resolver`] with an empty cache
+    pub fn new(config: ResolverConfig) -> Self {
+        let transport: Arc<dyn Transport> = match config.protocol {
+            Protocol::Tcp => Arc::new(Tcp::new()),
+            Protocol::Udp if config.udp_sockets > 0 => {
+                Arc::new(Multiplexer::new(config.udp_sockets))
+            }
+            Protocol::Udp => Arc::new(Udp::new()),
+        };
+        Self::with_transport(config, transport)
+    }
+
+    /// Creates a [`Resolver`] with an empty cache, exchanging messages with name servers over `transport`,
+    /// e.g. a `mock::Mock` (with the `mock` feature) to resolve names without a network.
+    ///
+    /// [`ResolverConfig::udp_sockets`] is left to the transport. [`Resolver::resolve_async`] sends
+    /// its queries over `transport` too, see [`Transport::send_async`].
+    pub fn with_transport(config: ResolverConfig, transport: Arc<dyn Transport>) -> Self {
+        let cache = Mutex::new(Cache::with_capacity(config.cache_size));
+        Self {
+            config,
+            cache,
+            transport,
+        }
+    }
+
+    pub fn config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    /// Counts the messages received by this resolver's transport that did not answer its queries
+    pub fn discarded(&self) -> &Discarded {
+        self.transport.discarded()
+    }
+
+    /// Resolves `domain_name`'s records of type `record_type`, following any aliases on the way
+    pub fn resolve(&self, domain_name: &str, record_type: QType) -> Result<Lookup> {
+        block_on(self.run(domain_name, record_type, Io::Blocking))
+    }
+
+    /// Resolves every IPv4 and IPv6 address of `domain_name`, IPv4 addresses first.
+    ///
+    /// Fails only if neither kind of address could be resolved.
+    pub fn lookup_ip(&self, domain_name: &str) -> Result<Vec<IpAddr>> {
+        let v4 = self.resolve(domain_name, QType::A);
+        let v6 = self.resolve(domain_name, QType::AAAA);
+        Self::merge_ips(v4, v6)
+    }
+
+    /// Like [`Resolver::resolve`], but waits on name servers without blocking the thread,
+    /// so that many names may be resolved at once on a tokio runtime
+    #[cfg(feature = "tokio")]
+    pub async fn resolve_async(&self, domain_name: &str, record_type: QType) -> Result<Lookup> {
+        self.run(domain_name, record_type, Io::Tokio).await
+    }
+
+    /// Like [`Resolver::lookup_ip`], resolving both kinds of address at once
+    #[cfg(feature = "tokio")]
+    pub async fn lookup_ip_async(&self, domain_name: &str) -> Result<Vec<IpAddr>> {
+        let (v4, v6) = tokio::join!(
+            self.resolve_async(domain_name, QType::A),
+            self.resolve_async(domain_name, QType::AAAA)
+        );
+        Self::merge_ips(v4, v6)
+    }
+
+    async fn run(&self, domain_name: &str, record_type: QType, io: Io) -> Result<Lookup> {
+        let mut resolution = Resolution {
+            resolver: self,
+            io,
+            trace: Trace::default(),
+            stack: Vec::new(),
+        };
+        let name = DomainName::parse(domain_name).map_err(|source| ResolveError::InvalidName {
+            name: domain_name.to_string(),
+            source,
+            trace: Box::default(),
+        })?;
+        let mut lookup = resolution.resolve(&name, record_type, 0).await?;
+        lookup.trace = resolution.trace;
+        Ok(lookup)
+    }
+
+    fn merge_ips(v4: Result<Lookup>, v6: Result<Lookup>) -> Result<Vec<IpAddr>> {
+        match (v4, v6) {
+            (Err(err), Err(_)) => Err(err),
+            (v4, v6) => Ok(v4
+                .iter()
+                .chain(v6.iter())
+                .flat_map(Lookup::ip_addrs)
+                .collect()),
+        }
+    }
+
+    /// Forgets every cached answer and referral
+    pub fn clear_cache(&self) {
+        self.cache().clear();
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        // the cache is left consistent even if a holder panicked
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A [`Resolver`] using the default [`ResolverConfig`], shared by all callers
+fn default_resolver() -> &'static Resolver {
+    static RESOLVER: OnceLock<Resolver> = OnceLock::new();
+    RESOLVER.get_or_init(Resolver::default)
+}
+
+/// Resolves `domain_name`'s records of type `record_type` with the default [`Resolver`]
+pub fn resolve(domain_name: &str, record_type: QType) -> Result<Lookup> {
+    default_resolver().resolve(domain_name, record_type)
+}
+
+/// Resolves every IPv4 and IPv6 address of `domain_name` with the default [`Resolver`]
+pub fn lookup_ip(domain_name: &str) -> Result<Vec<IpAddr>> {
+    default_resolver().lookup_ip(domain_name)
+}
+
+/// Drives `future` to completion on the calling thread, parking it while the future waits to be woken.
+///
+/// A resolution whose queries block the thread is done after the first poll, but one that waits
+/// on anything else is still woken like on any executor.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut context = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            // a wakeup arriving before the thread parks makes it return right away
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// Wakes the thread running [`block_on`]
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// How a resolution exchanges messages with name servers
+#[derive(Debug, Clone, Copy)]
+enum Io {
+    /// over the resolver's [`Transport`]
+    Blocking,
+    /// over the resolver's [`Transport`], without blocking
+    #[cfg(feature = "tokio")]
+    Tokio,
+}
+
+/// The aliases leading from a name to its canonical name
+#[derive(Debug, Default)]
+struct Chain {
+    /// in order, including those synthesized from `dnames`
+    cnames: Vec<Record>,
+    dnames: Vec<Record>,
+}
+
+impl Chain {
+    /// The name the last alias points to
+    fn target(&self) -> Option<&DomainName> {
+        self.cnames.last().and_then(|rr| rr.rdata.as_name())
+    }
+}
+
+/// A name server to query, by address or by a name yet to be resolved
+#[derive(Debug, Clone)]
+enum Nameserver {
+    Addr(IpAddr),
+    Name(DomainName),
+}
+
+/// The state of a single call to [`Resolver::resolve`], shared by the resolutions of the names it depends on
+struct Resolution<'r> {
+    resolver: &'r Resolver,
+    io: Io,
+    trace: Trace,
+    /// the names currently being resolved, outermost first
+    stack: Vec<(DomainName, QType)>,
+}
+
+impl Resolution<'_> {
+    /// Resolves `name`, reached by following `aliases` aliases from the name being looked up.
+    ///
+    /// Boxed, as resolving a name may require resolving others first
+    fn resolve<'a>(
+        &'a mut self,
+        name: &'a DomainName,
+        record_type: QType,
+        aliases: usize,
+    ) -> Pin<Box<dyn Future<Output = Result<Lookup>> + Send + 'a>> {
+        Box::pin(async move {
+            let key = (name.clone(), record_type);
+            if self.stack.contains(&key) {
+                return Err(ResolveError::LoopDetected {
+                    name: name.clone(),
+                    trace: Box::new(self.trace.clone()),
+                });
+            }
+            let max_depth = self.resolver.config.max_depth;
+            if self.stack.len() >= max_depth {
+                return Err(ResolveError::MaxDepthExceeded {
+                    depth: max_depth,
+                    trace: Box::new(self.trace.clone()),
+                });
+            }
+
+            self.stack.push(key);
+            let result = self.resolve_name(name, record_type, aliases).await;
+            self.stack.pop();
+            result
+        })
+    }
+
+    async fn resolve_name(
+        &mut self,
+        name: &DomainName,
+        record_type: QType,
+        aliases: usize,
+    ) -> Result<Lookup> {
+        let cached = self.resolver.cache().get(name, record_type, QClass::IN);
+        match cached {
+            Some(Cached::Records(records)) => {
+                return Ok(Lookup {
+                    name: name.clone(),
+                    qtype: record_type,
+                    records,
+                    cname_chain: Vec::new(),
+                    dnames: Vec::new(),
+                    server: None,
+                    authenticated: false,
+                    trace: Trace::default(),
+                })
+            }
+            Some(Cached::NoData { .. }) => return Err(self.no_data(name, record_type)),
+            Some(Cached::NxDomain { .. }) => return Err(self.nx_domain(name)),
+            None => {}
+        }
+
+        if let Some(chain) = self.cached_alias(name, record_type) {
+            return self.follow_cnames(name, chain, record_type, aliases).await;
+        }
+
+        let config = &self.resolver.config;
+        let preference = config.ip_preference;
+
+        // start from the closest zone cut we know of
+        let (mut zone, closest) = self
+            .resolver
+            .cache()
+            .nameservers(name, QClass::IN)
+            .map(|(zone, addrs)| (zone, preference.sort(addrs)))
+            .filter(|(_, addrs)| !addrs.is_empty())
+            .unwrap_or_else(|| {
+                let root_hints = config.root_hints.iter().copied();
+                (DomainName::root(), preference.sort(root_hints))
+            });
+        let mut nameservers: Vec<Nameserver> = closest.into_iter().map(Nameserver::Addr).collect();
+
+        // each referral followed takes another query
+        for _ in 0..=config.max_referrals {
+            let (nameserver, resp) = self
+                .query_nameservers(name, record_type, nameservers)
+                .await?;
+            let resp = self.filter_bailiwick(resp, name, record_type, &zone, nameserver);
+
+            self.resolver.cache().insert_message(&resp);
+
+            // the servers that failed were already passed over, but NXDOMAIN and the errors
+            // left, like FORMERR, won't be fixed by asking further
+            match resp.rcode() {
+                Rcode::NOERROR => {}
+                Rcode::NXDOMAIN => return Err(self.nx_domain(name)),
+                rcode => {
+                    return Err(ResolveError::ErrorResponse {
+                        server: nameserver,
+                        rcode,
+                        trace: Box::new(self.trace.clone()),
+                    })
+                }
+            }
+
+            // the answer may alias the name to another, possibly answering for that one too
+            let chain = self.alias_chain(&resp, name, record_type);
+            let target = chain.target().unwrap_or(name);
+            let records: Vec<Record> = resp
+                .answers
+                .iter()
+                .filter(|rr| rr.name == *target && rr.qtype == record_type)
+                .cloned()
+                .collect();
+
+            let referral = self.referral(&resp, name, &zone);
+
+            if !records.is_empty() {
+                self.count_cnames(name, aliases + chain.cnames.len())?;
+                return Ok(Lookup {
+                    name: name.clone(),
+                    qtype: record_type,
+                    records,
+                    cname_chain: chain.cnames,
+                    dnames: chain.dnames,
+                    server: Some(nameserver),
+                    authenticated: resp.header.flags.authentic_data(),
+                    trace: Trace::default(),
+                });
+            } else if !chain.cnames.is_empty() {
+                return self.follow_cnames(name, chain, record_type, aliases).await;
+            } else if let Some((child_zone, referred)) = referral {
+                zone = child_zone;
+                nameservers = referred;
+            } else if !resp.answers.is_empty()
+                || resp
+                    .get_record_by_type_from(QType::SOA, MsgSection::Authorities)
+                    .is_some()
+            {
+                return Err(self.no_data(name, record_type));
+            } else {
+                return Err(self.lame_delegation(name));
+            }
+        }
+
+        // referrals never reached an answer
+        Err(ResolveError::TooManyReferrals {
+            name: name.clone(),
+            max: self.resolver.config.max_referrals,
+            trace: Box::new(self.trace.clone()),
+        })
+    }
+
+    /// Removes the records `server` has no authority to give, recording them in the trace.
+    ///
+    /// A server may only speak for names within `zone`, the zone it was queried as a name server of.
+    /// Within it, answers must be owned by `name` or one of the aliases leading from it, and authority
+    /// records must belong to a zone enclosing one of those names, so that a response cannot answer
+    /// for or redirect other names to servers of the sender's choosing, see
+    /// [RFC 5452 section 6](https://datatracker.ietf.org/doc/html/rfc5452#section-6).
+    /// Additional records are only kept as the addresses of the name servers that remain.
+    fn filter_bailiwick(
+        &mut self,
+        mut resp: Message,
+        name: &DomainName,
+        record_type: QType,
+        zone: &DomainName,
+        server: IpAddr,
+    ) -> Message {
+        let mut rejected = Vec::new();
+        let mut accept = |record: &Record, in_bailiwick: bool| {
+            if !in_bailiwick {
+                rejected.push(Rejected {
+                    record: record.clone(),
+                    server,
+                    zone: zone.clone(),
+                });
+            }
+            in_bailiwick
+        };
+
+        let chain = self.alias_chain(&resp, name, record_type);
+        let queried: Vec<&DomainName> = std::iter::once(name)
+            .chain(chain.cnames.iter().filter_map(|rr| rr.rdata.as_name()))
+            .collect();
+        resp.answers.retain(|rr| {
+            let answers_query = queried.contains(&&rr.name) || chain.dnames.contains(rr);
+            accept(rr, rr.name.is_subdomain_of(zone) && answers_query)
+        });
+
+        resp.authorities.retain(|rr| {
+            let encloses_query = queried.iter().any(|name| name.is_subdomain_of(&rr.name));
+            accept(rr, rr.name.is_subdomain_of(zone) && encloses_query)
+        });
+
+        let nameservers: Vec<&DomainName> = resp
+            .authorities
+            .iter()
+            .filter(|rr| rr.qtype == QType::NS)
+            .filter_map(|rr| rr.rdata.as_name())
+            .collect();
+        resp.additionals.retain(|rr| {
+            let is_glue =
+                matches!(rr.qtype, QType::A | QType::AAAA) && nameservers.contains(&&rr.name);
+            accept(rr, rr.name.is_subdomain_of(zone) && is_glue)
+        });
+
+        self.trace.rejected.extend(rejected);
+        resp
+    }
+
+    /// The zone `resp` delegates `name` to and its name servers, those with glue addresses first.
+    ///
+    /// Only a delegation to a zone beneath `zone`, the zone of the server that sent `resp`, is followed.
+    fn referral(
+        &self,
+        resp: &Message,
+        name: &DomainName,
+        zone: &DomainName,
+    ) -> Option<(DomainName, Vec<Nameserver>)> {
+        let child_zone = resp
+            .authorities
+            .iter()
+            .filter(|rr| rr.qtype == QType::NS)
+            .map(|rr| &rr.name)
+            .find(|owner| {
+                *owner != zone && owner.is_subdomain_of(zone) && name.is_subdomain_of(owner)
+            })?;
+
+        let preference = self.resolver.config.ip_preference;
+        let mut with_glue = Vec::new();
+        let mut without_glue = Vec::new();
+
+        for ns in resp
+            .authorities
+            .iter()
+            .filter(|rr| rr.qtype == QType::NS && rr.name == *child_zone)
+            .filter_map(|rr| rr.rdata.as_name())
+        {
+            let glue = preference.sort(
+                resp.additionals
+                    .iter()
+                    .filter(|rr| rr.name == *ns && matches!(rr.qtype, QType::A | QType::AAAA))
+                    .filter_map(|rr| rr.rdata.as_ip_addr()),
+            );
+            if glue.is_empty() {
+                without_glue.push(Nameserver::Name(ns.clone()));
+            } else {
+                with_glue.extend(glue.into_iter().map(Nameserver::Addr));
+            }
+        }
+
+        with_glue.extend(without_glue);
+        Some((child_zone.clone(), with_glue))
+    }
+
+    /// Queries each of `nameservers` in turn until one answers, returning its address and response.
+    ///
+    /// A server responding with SERVFAIL, REFUSED or NOTIMP has failed like one that does not answer,
+    /// and is not asked again, see [RFC 1034 section 5.3.3](https://datatracker.ietf.org/doc/html/rfc1034#section-5.3.3).
+    /// Once every name server has failed to answer, another round of queries is sent,
+    /// waiting twice as long as the last, up to the configured number of retries.
+    /// Name servers without a known address are only resolved as they are reached in the first round.
+    async fn query_nameservers(
+        &mut self,
+        name: &DomainName,
+        record_type: QType,
+        nameservers: Vec<Nameserver>,
+    ) -> Result<(IpAddr, Message)> {
+        let config = &self.resolver.config;
+        let (base_timeout, retries) = (config.timeout, config.retries);
+
+        let mut pending = nameservers.into_iter();
+        let mut addrs: Vec<IpAddr> = Vec::new();
+        let mut last_err = None;
+
+        for round in 0..=retries {
+            let timeout = base_timeout.saturating_mul(1 << round.min(16));
+            let mut idx = 0;
+            loop {
+                if idx == addrs.len() {
+                    if round > 0 {
+                        break;
+                    }
+                    match self.next_addrs(&mut pending).await {
+                        Some(more) => addrs.extend(more),
+                        None => break,
+                    }
+                    continue;
+                }
+
+                let server = addrs[idx];
+                let result = self.send_query(name, server, record_type, timeout).await;
+                match result.and_then(|resp| self.check_server(server, resp)) {
+                    Ok(resp) => return Ok((server, resp)),
+                    Err(
+                        err @ (ResolveError::ServFail { .. } | ResolveError::ErrorResponse { .. }),
+                    ) => {
+                        addrs.remove(idx);
+                        last_err = Some(err);
+                    }
+                    Err(err) => {
+                        idx += 1;
+                        last_err = Some(err);
+                    }
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| self.lame_delegation(name)))
+    }
+
+    /// Fails if `server` responded that it cannot or will not answer
+    fn check_server(&self, server: IpAddr, resp: Message) -> Result<Message> {
+        match resp.rcode() {
+            Rcode::SERVFAIL => Err(ResolveError::ServFail {
+                server,
+                trace: Box::new(self.trace.clone()),
+            }),
+            rcode @ (Rcode::REFUSED | Rcode::NOTIMP) => Err(ResolveError::ErrorResponse {
+                server,
+                rcode,
+                trace: Box::new(self.trace.clone()),
+            }),
+            _ => Ok(resp),
+        }
+    }
+
+    /// The addresses of the next name server in `pending` that has any, resolving names as needed
+    async fn next_addrs(
+        &mut self,
+        pending: &mut (impl Iterator<Item = Nameserver> + Send),
+    ) -> Option<Vec<IpAddr>> {
+        let preference = self.resolver.config.ip_preference;
+
+        for nameserver in pending {
+            let addrs = match nameserver {
+                Nameserver::Addr(addr) => vec![addr],
+                // addresses of the less preferred family are only looked up if there are none of the other,
+                // and a name server that cannot be resolved is skipped, like one that does not answer.
+                // Its name is a lookup of its own, so aliases on the way to it count afresh
+                Nameserver::Name(ns) => {
+                    let mut addrs = Vec::new();
+                    for &record_type in preference.record_types() {
+                        if let Ok(lookup) = self.resolve(&ns, record_type, 0).await {
+                            addrs = preference.sort(lookup.ip_addrs());
+                        }
+                        if !addrs.is_empty() {
+                            break;
+                        }
+                    }
+                    addrs
+                }
+            };
+            if !addrs.is_empty() {
+                return Some(addrs);
+            }
+        }
+        None
+    }
+
+    /// The aliases in `resp`'s answers leading from `name` to its canonical name, in order, see
+    /// [RFC 1034 section 4.3.2](https://datatracker.ietf.org/doc/html/rfc1034#section-4.3.2).
+    ///
+    /// A DNAME record owned by an ancestor of a name in the chain redirects it, standing for a CNAME
+    /// record synthesized as described in [RFC 6672 section 3.4](https://datatracker.ietf.org/doc/html/rfc6672#section-3.4),
+    /// rather than the CNAME record given for it, if any. The chain is cut off once it holds more aliases
+    /// than may be followed.
+    fn alias_chain(&self, resp: &Message, name: &DomainName, record_type: QType) -> Chain {
+        let mut chain = Chain::default();
+        if record_type == QType::CNAME {
+            return chain;
+        }
+
+        let max = self.resolver.config.max_cname_chain;
+        let mut target = name.clone();
+        while chain.cnames.len() <= max {
+            let dname = resp
+                .answers
+                .iter()
+                .find(|rr| rr.qtype == QType::DNAME && Self::redirects(rr, &target));
+            let cname = match dname {
+                Some(dname) => {
+                    // a redirection too long to hold ends the chain
+                    let Some(cname) = dname.synthesize_cname(&target) else {
+                        break;
+                    };
+                    if !chain.dnames.contains(dname) {
+                        chain.dnames.push(dname.clone());
+                    }
+                    cname
+                }
+                None => match resp
+                    .answers
+                    .iter()
+                    .find(|rr| rr.name == target && rr.qtype == QType::CNAME)
+                {
+                    Some(cname) => cname.clone(),
+                    None => break,
+                },
+            };
+
+            // a chain looping back on itself in a single response ends where it repeats
+            if chain.cnames.iter().any(|rr| rr.name == cname.name) {
+                break;
+            }
+            let Some(next) = cname.rdata.as_name().cloned() else {
+                break;
+            };
+            chain.cnames.push(cname);
+            target = next;
+        }
+        chain
+    }
+
+    /// The alias for `name` in the cache: a CNAME record it owns,
+    /// or one synthesized from a DNAME record owned by one of its ancestors
+    fn cached_alias(&self, name: &DomainName, record_type: QType) -> Option<Chain> {
+        if record_type == QType::CNAME {
+            return None;
+        }
+        let cache = self.resolver.cache();
+        if let Some(Cached::Records(cnames)) = cache.get(name, QType::CNAME, QClass::IN) {
+            return Some(Chain {
+                cnames,
+                dnames: Vec::new(),
+            });
+        }
+
+        let mut ancestor = name.parent();
+        while let Some(owner) = ancestor {
+            if let Some(Cached::Records(dnames)) = cache.get(&owner, QType::DNAME, QClass::IN) {
+                let cname = dnames.first()?.synthesize_cname(name)?;
+                return Some(Chain {
+                    cnames: vec![cname],
+                    dnames,
+                });
+            }
+            ancestor = owner.parent();
+        }
+        None
+    }
+
+    /// Whether `dname` redirects `name`, i.e. `name` lies strictly beneath its owner
+    fn redirects(dname: &Record, name: &DomainName) -> bool {
+        name.num_labels() > dname.name.num_labels() && name.is_subdomain_of(&dname.name)
+    }
+
+    /// Resolves the canonical name at the end of `chain`, recording the aliases followed from `alias`,
+    /// which was itself reached by following `aliases` aliases
+    async fn follow_cnames(
+        &mut self,
+        alias: &DomainName,
+        chain: Chain,
+        record_type: QType,
+        aliases: usize,
+    ) -> Result<Lookup> {
+        let Some(target) = chain.target().cloned() else {
+            return Err(self.no_data(alias, record_type));
+        };
+        let aliases = aliases + chain.cnames.len();
+        self.count_cnames(alias, aliases)?;
+
+        let mut lookup = self.resolve(&target, record_type, aliases).await?;
+        lookup.name = alias.clone();
+        lookup.cname_chain.splice(0..0, chain.cnames);
+        lookup.dnames.splice(0..0, chain.dnames);
+        Ok(lookup)
+    }
+
+    /// Checks the `followed` aliases of a lookup against the configured maximum
+    fn count_cnames(&self, alias: &DomainName, followed: usize) -> Result<()> {
+        let max = self.resolver.config.max_cname_chain;
+        if followed > max {
+            return Err(ResolveError::CnameChainTooLong {
+                name: alias.clone(),
+                max,
+                trace: Box::new(self.trace.clone()),
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends a single query to `server_addr`, waiting at most `timeout` for the response
+    async fn send_query(
+        &mut self,
+        name: &DomainName,
+        server_addr: IpAddr,
+        record_type: QType,
+        timeout: Duration,
+    ) -> Result<Message> {
+        self.trace.servers.push(server_addr);
+
+        // advertise a payload size large enough that most answers need not be truncated,
+        // and ask for the AD bit to be set on authenticated answers, see
+        // [RFC 6840 section 5.7](https://datatracker.ietf.org/doc/html/rfc6840#section-5.7)
+        let config = &self.resolver.config;
+        let qname = if config.randomize_case {
+            name.randomize_case()
+        } else {
+            name.clone()
+        };
+        let query = crate::query_message(
+            qname,
+            record_type,
+            Flags::new().with_authentic_data(true),
+            Some(Edns::default()),
+        );
+
+        let socket_addr = SocketAddr::from((server_addr, config.port));
+        let options = transport::Options {
+            protocol: config.protocol,
+            timeout: Some(timeout),
+            exact_case: config.randomize_case,
+        };
+
+        let resp = match self.io {
+            Io::Blocking => self.resolver.transport.send(&query, socket_addr, &options),
+            #[cfg(feature = "tokio")]
+            Io::Tokio => {
+                let transport = &self.resolver.transport;
+                transport.send_async(&query, socket_addr, &options).await
+            }
+        };
+        let resp = resp.map_err(|source| {
+            let trace = Box::new(self.trace.clone());
+            match source {
+                message::Error::Io(source)
+                    if matches!(
+                        source.kind(),
+                        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    ResolveError::Timeout {
+                        server: server_addr,
+                        trace,
+                    }
+                }
+                message::Error::Io(source) => ResolveError::Io {
+                    server: server_addr,
+                    source,
+                    trace,
+                },
+                source => ResolveError::MalformedResponse {
+                    server: server_addr,
+                    source,
+                    trace,
+                },
+            }
+        })?;
+
+        self.trace.last_response = Some(Box::new(resp.clone()));
+        Ok(resp)
+    }
+
+    fn nx_domain(&self, name: &DomainName) -> ResolveError {
+        ResolveError::NxDomain {
+            name: name.clone(),
+            trace: Box::new(self.trace.clone()),
+        }
+    }
+
+    fn lame_delegation(&self, zone: &DomainName) -> ResolveError {
+        ResolveError::LameDelegation {
+            zone: zone.clone(),
+            trace: Box::new(self.trace.clone()),
+        }
+    }
+
+    fn no_data(&self, name: &DomainName, qtype: QType) -> ResolveError {
+        ResolveError::NoData {
+            name: name.clone(),
+            qtype,
+            trace: Box::new(self.trace.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, UdpSocket};
+
+    use super::*;
+    use crate::{
+        cache::Trust,
+        mock::Mock,
+        rdata::RData,
+        record::Record,
+        test_util::{record, respond_once},
+    };
+
+    /// A [`ResolverConfig`] that starts from a single server on localhost
+    fn local_config(server: &UdpSocket) -> std::io::Result<ResolverConfig> {
+        Ok(ResolverConfig {
+            root_hints: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            port: server.local_addr()?.port(),
+            timeout: Duration::from_millis(100),
+            ..ResolverConfig::default()
+        })
+    }
+
+    /// Answers a single query on `server` with `answers`, authoritatively
+    fn answer_once(
+        server: UdpSocket,
+        answers: Vec<Record>,
+    ) -> std::thread::JoinHandle<message::Result<()>> {
+        respond_once(server, move |resp| {
+            resp.header.flags = resp.header.flags.with_authoritative(true);
+            resp.answers = answers;
+        })
+    }
+
+    #[test]
+    fn resolves_from_configured_root_and_caches(
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let resolver = Resolver::new(local_config(&server)?);
+        let answer = Ipv4Addr::new(192, 0, 2, 1);
+
+        // answers exactly one query, so a second lookup must come from the cache
+        let server_thread = answer_once(
+            server,
+            vec![record("www.example.com", 300, RData::A(answer))],
+        );
+
+        let lookup = resolver.resolve("www.example.com", QType::A)?;
+        server_thread.join().unwrap()?;
+        assert_eq!(lookup.ip_addrs().collect::<Vec<_>>(), vec![answer]);
+        assert_eq!(lookup.server, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert_eq!(lookup.min_ttl(), Some(300));
+
+        let cached = resolver.resolve("www.example.com", QType::A)?;
+        assert_eq!(cached.ip_addrs().collect::<Vec<_>>(), vec![answer]);
+        assert_eq!(cached.server, None);
+
+        // a differently configured resolver does not share the cache
+        let other = Resolver::new(ResolverConfig {
+            retries: 0,
+            ..resolver.config().clone()
+        });
+        assert!(other.resolve("www.example.com", QType::A).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn follows_cnames_within_answer() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let resolver = Resolver::new(local_config(&server)?);
+        let chain = vec![
+            record(
+                "www.example.com",
+                300,
+                RData::CNAME(DomainName::new("web.example.com")),
+            ),
+            record(
+                "web.example.com",
+                60,
+                RData::CNAME(DomainName::new("cdn.example.net")),
+            ),
+        ];
+        let addrs = vec![
+            record(
+                "cdn.example.net",
+                120,
+                RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            ),
+            record(
+                "cdn.example.net",
+                120,
+                RData::A(Ipv4Addr::new(192, 0, 2, 2)),
+            ),
+        ];
+        let server_thread = answer_once(server, [chain.clone(), addrs.clone()].concat());
+
+        let lookup = resolver.resolve("www.example.com", QType::A)?;
+        server_thread.join().unwrap()?;
+
+        assert_eq!(lookup.name, DomainName::new("www.example.com"));
+        assert_eq!(lookup.canonical_name(), &DomainName::new("cdn.example.net"));
+        assert_eq!(lookup.cname_chain, chain);
+        assert_eq!(lookup.records, addrs);
+        assert_eq!(lookup.min_ttl(), Some(60));
+        Ok(())
+    }
+
+    #[test]
+    fn moves_on_to_next_nameserver() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        // both servers listen on the same port, as name servers do
+        let silent = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = silent.local_addr()?.port();
+        let server = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, 2), port))?;
+
+        let silent_ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let server_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        let resolver = Resolver::new(ResolverConfig {
+            root_hints: vec![silent_ip, server_ip],
+            ..local_config(&silent)?
+        });
+
+        let answer = Ipv4Addr::new(192, 0, 2, 1);
+        let server_thread = answer_once(
+            server,
+            vec![record("www.example.com", 300, RData::A(answer))],
+        );
+
+        let lookup = resolver.resolve("www.example.com", QType::A)?;
+        server_thread.join().unwrap()?;
+
+        assert_eq!(lookup.server, Some(server_ip));
+        assert_eq!(lookup.ip_addrs().collect::<Vec<_>>(), vec![answer]);
+        Ok(())
+    }
+
+    #[test]
+    fn moves_on_from_failing_nameservers() -> Result<()> {
+        let ip = |last| IpAddr::V4(Ipv4Addr::new(192, 0, 2, last));
+        let answer = record(
+            "www.example.com",
+            300,
+            RData::A(Ipv4Addr::new(192, 0, 2, 80)),
+        );
+        let mock = Arc::new(Mock::new());
+        for (last, rcode) in [
+            (1, Rcode::SERVFAIL),
+            (2, Rcode::REFUSED),
+            (3, Rcode::NOTIMP),
+        ] {
+            mock.script(ip(last), move |query| {
+                let mut resp = Mock::reply_to(query);
+                resp.header.flags = resp.header.flags.with_rcode(rcode);
+                Some(resp)
+            });
+        }
+        mock.serve_zone(ip(4), DomainName::root(), vec![answer.clone()]);
+        let resolver = |root_hints| {
+            Resolver::with_transport(
+                ResolverConfig {
+                    root_hints,
+                    retries: 1,
+                    ..ResolverConfig::default()
+                },
+                mock.clone(),
+            )
+        };
+
+        let lookup =
+            resolver(vec![ip(1), ip(2), ip(3), ip(4)]).resolve("www.example.com", QType::A)?;
+        assert_eq!(lookup.server, Some(ip(4)));
+        assert_eq!(lookup.records, vec![answer]);
+
+        // the lookup only fails once every server has, none of them asked twice
+        let err = resolver(vec![ip(2), ip(1)])
+            .resolve("www.example.net", QType::A)
+            .unwrap_err();
+        assert!(
+            matches!(err, ResolveError::ServFail { server, .. } if server == ip(1)),
+            "{err}"
+        );
+        assert_eq!(err.server_chain().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_bailiwick_records_rejected() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let root = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let port = root.local_addr()?.port();
+        let example_server = UdpSocket::bind((Ipv4Addr::new(127, 0, 0, 2), port))?;
+        let resolver = Resolver::new(local_config(&root)?);
+
+        let ns = |zone, host| record(zone, 3600, RData::NS(DomainName::new(host)));
+        let a = |name, ip| record(name, 3600, RData::A(ip));
+        let spoofed = Ipv4Addr::new(203, 0, 113, 66);
+
+        // the root refers to example.com, also trying to take over example.org
+        // and to answer for a name within its zone that was not asked for
+        let root_thread = respond_once(root, move |resp| {
+            resp.answers = vec![a("www.bank.com", spoofed)];
+            resp.authorities = vec![
+                ns("example.com", "ns.example.com"),
+                ns("example.org", "ns.example.com"),
+            ];
+            resp.additionals = vec![
+                a("ns.example.com", Ipv4Addr::new(127, 0, 0, 2)),
+                a("www.bank.com", spoofed),
+            ];
+        });
+        // example.com answers, also trying to answer for example.org and other names of its own
+        let example_thread = respond_once(example_server, move |resp| {
+            resp.header.flags = resp.header.flags.with_authoritative(true);
+            resp.answers = vec![
+                a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)),
+                a("www.example.org", spoofed),
+                a("mail.example.com", spoofed),
+            ];
+            resp.additionals = vec![a("ns.example.org", spoofed), a("ftp.example.com", spoofed)];
+        });
+
+        let lookup = resolver.resolve("www.example.com", QType::A)?;
+        root_thread.join().unwrap()?;
+        example_thread.join().unwrap()?;
+
+        assert_eq!(
+            lookup.ip_addrs().collect::<Vec<_>>(),
+            vec![Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        let rejected: Vec<(DomainName, DomainName)> = lookup
+            .trace
+            .rejected
+            .iter()
+            .map(|rejected| (rejected.record.name.clone(), rejected.zone.clone()))
+            .collect();
+        assert_eq!(
+            rejected,
+            [
+                ("www.bank.com", "."),
+                ("example.org", "."),
+                ("www.bank.com", "."),
+                ("www.example.org", "example.com"),
+                ("mail.example.com", "example.com"),
+                ("ns.example.org", "example.com"),
+                ("ftp.example.com", "example.com"),
+            ]
+            .map(|(name, zone)| (DomainName::new(name), DomainName::new(zone)))
+        );
+
+        // not even as glue
+        let cache = resolver.cache();
+        let cached = |name, qtype| {
+            cache.get_trusted(&DomainName::new(name), qtype, QClass::IN, Trust::Additional)
+        };
+        assert_eq!(cached("www.bank.com", QType::A), None);
+        assert_eq!(cached("mail.example.com", QType::A), None);
+        assert_eq!(cached("ftp.example.com", QType::A), None);
+        assert_eq!(cached("www.example.org", QType::A), None);
+        assert_eq!(cached("ns.example.org", QType::A), None);
+        assert_eq!(cached("example.org", QType::NS), None);
+        Ok(())
+    }
+
+    #[test]
+    fn shares_sockets_between_threads() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let resolver = Resolver::new(ResolverConfig {
+            udp_sockets: 2,
+            ..local_config(&server)?
+        });
+        let answer = Ipv4Addr::new(192, 0, 2, 1);
+
+        // a single query answers both threads, whether sent once or answered from the cache
+        let server_thread = answer_once(
+            server,
+            vec![record("www.example.com", 300, RData::A(answer))],
+        );
+        std::thread::scope(|scope| {
+            let lookups: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| resolver.resolve("www.example.com", QType::A)))
+                .collect();
+            for lookup in lookups {
+                let lookup = lookup.join().unwrap()?;
+                assert_eq!(lookup.ip_addrs().collect::<Vec<_>>(), vec![answer]);
+            }
+            Result::Ok(())
+        })?;
+        server_thread.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_through_mock_hierarchy() -> Result<()> {
+        let ip = |last| IpAddr::V4(Ipv4Addr::new(192, 0, 2, last));
+        let ns = |zone, host| record(zone, 3600, RData::NS(DomainName::new(host)));
+        let a = |name, last| record(name, 3600, RData::A(Ipv4Addr::new(192, 0, 2, last)));
+        let (root, com, net, example_net, example_com) = (ip(1), ip(2), ip(3), ip(4), ip(5));
+
+        let mock = Arc::new(Mock::new());
+        mock.serve_zone(
+            root,
+            DomainName::root(),
+            vec![
+                ns("com", "a.gtld-servers.net"),
+                ns("net", "b.gtld-servers.net"),
+                a("a.gtld-servers.net", 2),
+                a("b.gtld-servers.net", 3),
+            ],
+        );
+        mock.serve_zone(
+            com,
+            DomainName::new("com"),
+            // no glue, the name server is in another zone
+            vec![ns("example.com", "ns1.example.net")],
+        );
+        mock.serve_zone(
+            net,
+            DomainName::new("net"),
+            vec![ns("example.net", "ns.example.net"), a("ns.example.net", 4)],
+        );
+        mock.serve_zone(
+            example_net,
+            DomainName::new("example.net"),
+            vec![
+                ns("example.net", "ns.example.net"),
+                a("ns.example.net", 4),
+                a("ns1.example.net", 5),
+                a("cdn.example.net", 80),
+            ],
+        );
+        let alias = record(
+            "www.example.com",
+            300,
+            RData::CNAME(DomainName::new("cdn.example.net")),
+        );
+        mock.serve_zone(
+            example_com,
+            DomainName::new("example.com"),
+            vec![alias.clone()],
+        );
+
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![root],
+                ..ResolverConfig::default()
+            },
+            mock.clone(),
+        );
+        let lookup = resolver.resolve("www.example.com", QType::A)?;
+
+        assert_eq!(lookup.cname_chain, vec![alias]);
+        assert_eq!(
+            lookup.ip_addrs().collect::<Vec<_>>(),
+            vec![Ipv4Addr::new(192, 0, 2, 80)]
+        );
+        assert_eq!(lookup.server, Some(example_net));
+        let queried: Vec<(IpAddr, String)> = mock
+            .queries()
+            .into_iter()
+            .map(|(server, question)| (server, question.qname.to_string()))
+            .collect();
+        let expected = [
+            (root, "www.example.com"),
+            (com, "www.example.com"),
+            // the name server of example.com is resolved from the root too
+            (root, "ns1.example.net"),
+            (net, "ns1.example.net"),
+            (example_net, "ns1.example.net"),
+            (example_com, "www.example.com"),
+            // the alias' target is resolved from the closest cached zone cut
+            (example_net, "cdn.example.net"),
+        ];
+        assert_eq!(
+            queried,
+            expected.map(|(server, name)| (server, name.to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resolves_aaaa_over_ipv6_nameservers() -> Result<()> {
+        let ip = |last| Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last);
+        let ns = |zone, host| record(zone, 3600, RData::NS(DomainName::new(host)));
+        let aaaa = |name, last| record(name, 3600, RData::AAAA(ip(last)));
+        let soa = |zone| {
+            let rdata = RData::SOA {
+                mname: DomainName::new("ns.net"),
+                rname: DomainName::new("hostmaster.net"),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            };
+            record(zone, 3600, rdata)
+        };
+
+        // every name server is reachable over IPv6 only
+        let mock = Arc::new(Mock::new());
+        mock.serve_zone(
+            ip(1).into(),
+            DomainName::root(),
+            vec![
+                ns("example", "ns.example"),
+                aaaa("ns.example", 2),
+                ns("net", "ns.net"),
+                aaaa("ns.net", 3),
+            ],
+        );
+        mock.serve_zone(
+            ip(2).into(),
+            DomainName::new("example"),
+            vec![
+                aaaa("www.example", 80),
+                // no glue, and the name server has no IPv4 address
+                ns("sub.example", "ns.sub.net"),
+            ],
+        );
+        mock.serve_zone(
+            ip(3).into(),
+            DomainName::new("net"),
+            vec![soa("net"), aaaa("ns.sub.net", 4)],
+        );
+        mock.serve_zone(
+            ip(4).into(),
+            DomainName::new("sub.example"),
+            vec![aaaa("host.sub.example", 81)],
+        );
+
+        // the IPv4 root does not answer
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![IpAddr::V6(ip(1)), IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))],
+                ..ResolverConfig::default()
+            },
+            mock.clone(),
+        );
+
+        let lookup = resolver.resolve("www.example", QType::AAAA)?;
+        assert_eq!(lookup.ip_addrs().collect::<Vec<_>>(), vec![ip(80)]);
+        assert_eq!(
+            lookup.trace.servers,
+            vec![
+                IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+                ip(1).into(),
+                ip(2).into()
+            ]
+        );
+
+        let lookup = resolver.resolve("host.sub.example", QType::AAAA)?;
+        assert_eq!(lookup.ip_addrs().collect::<Vec<_>>(), vec![ip(81)]);
+        assert_eq!(lookup.server, Some(ip(4).into()));
+
+        let ipv4_only = Resolver::with_transport(
+            ResolverConfig {
+                ip_preference: IpPreference::Ipv4Only,
+                ..resolver.config().clone()
+            },
+            mock,
+        );
+        assert!(ipv4_only.resolve("www.example", QType::AAAA).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn follows_dnames_across_zones() -> Result<()> {
+        let (com, net) = (
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+        let alias = |name, target| record(name, 300, RData::CNAME(DomainName::new(target)));
+        let a = |name, last| record(name, 300, RData::A(Ipv4Addr::new(192, 0, 2, last)));
+        let dname = record(
+            "old.example.com",
+            60,
+            RData::DNAME(DomainName::new("new.example.net")),
+        );
+
+        let mock = Arc::new(Mock::new());
+        mock.serve_zone(
+            com,
+            DomainName::root(),
+            vec![
+                record("example.net", 3600, RData::NS(DomainName::new("ns.net"))),
+                a("ns.net", 2),
+                dname.clone(),
+            ],
+        );
+        mock.serve_zone(
+            net,
+            DomainName::new("example.net"),
+            vec![
+                alias("www.new.example.net", "host.example.net"),
+                a("host.example.net", 80),
+                a("mail.new.example.net", 25),
+            ],
+        );
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![com],
+                ..ResolverConfig::default()
+            },
+            mock.clone(),
+        );
+
+        let lookup = resolver.resolve("www.old.example.com", QType::A)?;
+        assert_eq!(lookup.dnames, vec![dname.clone()]);
+        assert_eq!(
+            lookup.cname_chain,
+            vec![
+                // synthesized from the DNAME, with its TTL
+                record(
+                    "www.old.example.com",
+                    60,
+                    RData::CNAME(DomainName::new("www.new.example.net"))
+                ),
+                alias("www.new.example.net", "host.example.net"),
+            ]
+        );
+        assert_eq!(lookup.records, vec![a("host.example.net", 80)]);
+        assert_eq!(lookup.min_ttl(), Some(60));
+
+        // the cached DNAME redirects other names without asking its zone again
+        let lookup = resolver.resolve("mail.old.example.com", QType::A)?;
+        let dnames: Vec<_> = lookup.dnames.iter().map(|rr| &rr.rdata).collect();
+        assert_eq!(dnames, vec![&dname.rdata]);
+        assert_eq!(lookup.records, vec![a("mail.new.example.net", 25)]);
+        assert_eq!(lookup.trace.servers, vec![net]);
+        Ok(())
+    }
+
+    #[test]
+    fn alias_loops_and_long_chains_rejected() {
+        let server = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let alias = |name, target| record(name, 300, RData::CNAME(DomainName::new(target)));
+        let mock = Arc::new(Mock::new());
+        mock.serve_zone(
+            server,
+            DomainName::root(),
+            vec![
+                alias("a.example", "b.example"),
+                alias("b.example", "a.example"),
+                alias("one.example", "two.example"),
+                alias("two.example", "three.example"),
+                alias("three.example", "four.example"),
+                record("four.example", 300, RData::A(Ipv4Addr::new(192, 0, 2, 4))),
+                // each redirection makes the name longer, so that it never repeats
+                record("x", 300, RData::DNAME(DomainName::new("x.x"))),
+            ],
+        );
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![server],
+                max_cname_chain: 2,
+                ..ResolverConfig::default()
+            },
+            mock,
+        );
+
+        let err = resolver.resolve("a.example", QType::A).unwrap_err();
+        assert!(matches!(err, ResolveError::LoopDetected { .. }), "{err}");
+        let err = resolver.resolve("one.example", QType::A).unwrap_err();
+        assert!(
+            matches!(err, ResolveError::CnameChainTooLong { max: 2, .. }),
+            "{err}"
+        );
+        let err = resolver.resolve("www.x", QType::A).unwrap_err();
+        assert!(
+            matches!(err, ResolveError::CnameChainTooLong { max: 2, .. }),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn aliases_counted_per_lookup() -> Result<()> {
+        let (root, sub) = (
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+        let alias = |name, target| record(name, 300, RData::CNAME(DomainName::new(target)));
+        let a = |name, last| record(name, 300, RData::A(Ipv4Addr::new(192, 0, 2, last)));
+        let mock = Arc::new(Mock::new());
+        mock.serve_zone(
+            root,
+            DomainName::root(),
+            vec![
+                alias("www.example", "host.sub.example"),
+                record("sub.example", 300, RData::NS(DomainName::new("ns.example"))),
+                // the name server is an alias too, which is no part of the chain of www.example
+                alias("ns.example", "ns2.example"),
+                a("ns2.example", 2),
+            ],
+        );
+        mock.serve_zone(
+            sub,
+            DomainName::new("sub.example"),
+            vec![a("host.sub.example", 80)],
+        );
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![root],
+                max_cname_chain: 1,
+                ..ResolverConfig::default()
+            },
+            mock,
+        );
+
+        let lookup = resolver.resolve("www.example", QType::A)?;
+        assert_eq!(lookup.cname_chain.len(), 1);
+        assert_eq!(lookup.records, vec![a("host.sub.example", 80)]);
+        Ok(())
+    }
+
+    #[test]
+    fn long_referral_chains_rejected() -> Result<()> {
+        let ip = |k: usize| Ipv4Addr::new(10, 0, 0, k as u8 + 1);
+        let name = "a.".repeat(5);
+        let answer = record(&name, 300, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        let mock = Arc::new(Mock::new());
+        // each zone delegates the name one label longer to the next server, the last one answers
+        for k in 0..5 {
+            let (zone, child) = ("a.".repeat(k), "a.".repeat(k + 1));
+            let ns = format!("ns.{child}");
+            mock.serve_zone(
+                IpAddr::V4(ip(k)),
+                DomainName::new(&zone),
+                vec![
+                    record(&child, 300, RData::NS(DomainName::new(&ns))),
+                    record(&ns, 300, RData::A(ip(k + 1))),
+                ],
+            );
+        }
+        mock.serve_zone(
+            IpAddr::V4(ip(5)),
+            DomainName::new(&name),
+            vec![answer.clone()],
+        );
+        let resolver = |max_referrals| {
+            Resolver::with_transport(
+                ResolverConfig {
+                    root_hints: vec![IpAddr::V4(ip(0))],
+                    max_referrals,
+                    ..ResolverConfig::default()
+                },
+                mock.clone(),
+            )
+        };
+
+        let err = resolver(4).resolve(&name, QType::A).unwrap_err();
+        assert!(
+            matches!(err, ResolveError::TooManyReferrals { max: 4, .. }),
+            "{err}"
+        );
+        let lookup = resolver(5).resolve(&name, QType::A)?;
+        assert_eq!(lookup.records, vec![answer]);
+        Ok(())
+    }
+
+    #[test]
+    fn blocking_resolution_waits_to_be_woken() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // pending until another thread marks it ready and wakes it
+        let ready = Arc::new(AtomicBool::new(false));
+        let mut waking = None;
+        let output = block_on(std::future::poll_fn(|cx| {
+            if ready.load(Ordering::Acquire) {
+                return Poll::Ready(42);
+            }
+            if waking.is_none() {
+                let (ready, waker) = (Arc::clone(&ready), cx.waker().clone());
+                waking = Some(std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(50));
+                    ready.store(true, Ordering::Release);
+                    waker.wake();
+                }));
+            }
+            Poll::Pending
+        }));
+        assert_eq!(output, 42);
+    }
+
+    #[test]
+    fn invalid_name_rejected() {
+        let resolver = Resolver::new(ResolverConfig {
+            root_hints: Vec::new(),
+            ..ResolverConfig::default()
+        });
+        let err = resolver.resolve("www..example.com", QType::A).unwrap_err();
+        assert!(matches!(err, ResolveError::InvalidName { .. }), "{err}");
+        assert!(err.server_chain().is_empty());
+    }
+
+    #[test]
+    fn retries_after_timeout() -> std::io::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let config = ResolverConfig {
+            retries: 2,
+            ..local_config(&server)?
+        };
+
+        let start = std::time::Instant::now();
+        let err = Resolver::new(config)
+            .resolve("www.example.com", QType::A)
+            .unwrap_err();
+
+        // one query per round, each round waiting twice as long as the last
+        assert!(matches!(err, ResolveError::Timeout { .. }), "{err}");
+        assert_eq!(err.server_chain().len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(100 + 200 + 400));
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn resolves_async_over_transport() -> Result<()> {
+        let (stray, root) = (
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+        let a = |name, last| record(name, 300, RData::A(Ipv4Addr::new(192, 0, 2, last)));
+        let mock = Arc::new(Mock::new());
+        // responds with the wrong ID, so that each query to it times out
+        mock.script(stray, |query| {
+            let mut resp = Mock::reply_to(query);
+            resp.header.id = query.header.id.wrapping_add(1);
+            Some(resp)
+        });
+        mock.serve_zone(
+            root,
+            DomainName::root(),
+            vec![a("a.example.com", 1), a("b.example.com", 2)],
+        );
+        let resolver = Resolver::with_transport(
+            ResolverConfig {
+                root_hints: vec![stray, root],
+                ..ResolverConfig::default()
+            },
+            mock.clone(),
+        );
+
+        let (a, b) = tokio::join!(
+            resolver.resolve_async("a.example.com", QType::A),
+            resolver.resolve_async("b.example.com", QType::A)
+        );
+        assert_eq!(
+            a?.ip_addrs().collect::<Vec<_>>(),
+            [Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        assert_eq!(
+            b?.ip_addrs().collect::<Vec<_>>(),
+            [Ipv4Addr::new(192, 0, 2, 2)]
+        );
+        // every query went to the mock, which counted what it discarded for the resolver
+        assert_eq!(mock.queries().len(), 4);
+        assert_eq!(resolver.discarded().wrong_id(), 2);
+
+        // the answers are cached for blocking callers too
+        let cached = resolver.resolve("a.example.com", QType::A)?;
+        assert_eq!(cached.server, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+//! Helpers shared by the unit tests of several modules.
+//!
+//! Name servers are stood in for either by a [`Mock`](crate::mock::Mock), or by a thread
+//! answering on a local UDP socket like those started by [`respond_once`].
+
+use std::{
+    io::Cursor,
+    net::{SocketAddr, UdpSocket},
+    thread::JoinHandle,
+};
+
+use crate::{
+    dname::DomainName,
+    header::Flags,
+    message::{self, Message},
+    qclass::QClass,
+    rdata::RData,
+    record::Record,
+};
+
+/// A record of class IN for `name`, of the type of `rdata`
+pub(crate) fn record(name: &str, time_to_live: u32, rdata: RData) -> Record {
+    Record {
+        name: DomainName::new(name),
+        qtype: rdata.rtype(),
+        class: QClass::IN,
+        time_to_live,
+        rdata,
+    }
+}
+
+/// Waits for the next query on `server`, returning it along with the address to respond to
+pub(crate) fn recv_query(server: &UdpSocket) -> message::Result<(Message, SocketAddr)> {
+    let mut buf = [0u8; 512];
+    let (size, client) = server.recv_from(&mut buf)?;
+    let query = Message::from_bytes(&mut Cursor::new(&buf[..size]))?;
+    Ok((query, client))
+}
+
+/// Responds to a single query on `server` from another thread, filling in the response with `fill`
+pub(crate) fn respond_once(
+    server: UdpSocket,
+    fill: impl FnOnce(&mut Message) + Send + 'static,
+) -> JoinHandle<message::Result<()>> {
+    std::thread::spawn(move || {
+        let (mut resp, client) = recv_query(&server)?;
+        resp.header.flags = Flags::new().with_response(true);
+        fill(&mut resp);
+        server.send_to(&resp.to_bytes()?, client)?;
+        Ok(())
+    })
+}
@@ -0,0 +1,1347 @@
+//! Carries messages between the resolver and name servers.
+//!
+//! Queries are sent over UDP by default, from a socket of the same address family as the server,
+//! IPv4 or IPv6. Messages sent over TCP are prefixed
+//! with a two byte length field, as described in
+//! [RFC 1035 section 4.2.2](https://datatracker.ietf.org/doc/html/rfc1035#section-4.2.2).
+//!
+//! A server sets the TC bit when a response does not fit in a UDP datagram,
+//! in which case the query is repeated over TCP, see
+//! [RFC 7766 section 5](https://datatracker.ietf.org/doc/html/rfc7766#section-5).
+//!
+//! A response is only accepted if it comes from the server queried, has the QR bit set,
+//! carries the query's ID and echoes its question section, see
+//! [RFC 5452 section 4](https://datatracker.ietf.org/doc/html/rfc5452#section-4).
+//! Anything else arriving over UDP is discarded while waiting for the real response.
+//!
+//! The [`Transport`] trait abstracts the exchange, so that resolution does not depend on how
+//! messages reach name servers: [`Udp`] and [`Tcp`] use a fresh socket per query, while a
+//! [`Multiplexer`] sends queries over a pool of long-lived sockets and sends identical queries
+//! in flight at the same time only once. With the `mock` feature, `mock::Mock` answers from memory instead.
+//!
+//! The functions here block the calling thread; with the `tokio` feature, [`nonblocking`]
+//! offers the same exchanges on a tokio runtime, and transports send queries through
+//! `Transport::send_async` without blocking.
+
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{
+    header::Header,
+    message::{self, Message},
+    record::Edns,
+};
+
+/// The port name servers listen on for both UDP and TCP
+pub const DNS_PORT: u16 = 53;
+
+/// How queries are sent to name servers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Send over UDP, repeating the query over TCP if the response is truncated
+    #[default]
+    Udp,
+    /// Always send over TCP
+    Tcp,
+}
+
+/// How a query is sent and its response matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Ignored by transports that only speak one protocol, e.g. [`Tcp`]
+    pub protocol: Protocol,
+    /// How long to wait for the response, indefinitely if [`None`]
+    pub timeout: Option<Duration>,
+    /// Whether the response must echo the question's name in exactly the case it was sent,
+    /// rather than merely an equal name
+    pub exact_case: bool,
+}
+
+/// Counts the messages received but discarded for not being the response to a query
+#[derive(Debug, Default)]
+pub struct Discarded {
+    wrong_source: AtomicU64,
+    malformed: AtomicU64,
+    not_response: AtomicU64,
+    wrong_id: AtomicU64,
+    wrong_question: AtomicU64,
+}
+
+impl Discarded {
+    /// Datagrams from an address other than the server queried
+    pub fn wrong_source(&self) -> u64 {
+        self.wrong_source.load(Ordering::Relaxed)
+    }
+
+    /// Datagrams that fail to parse as a message
+    pub fn malformed(&self) -> u64 {
+        self.malformed.load(Ordering::Relaxed)
+    }
+
+    /// Messages without the QR bit set
+    pub fn not_response(&self) -> u64 {
+        self.not_response.load(Ordering::Relaxed)
+    }
+
+    /// Responses whose ID differs from the query's
+    pub fn wrong_id(&self) -> u64 {
+        self.wrong_id.load(Ordering::Relaxed)
+    }
+
+    /// Responses whose question section differs from the query's
+    pub fn wrong_question(&self) -> u64 {
+        self.wrong_question.load(Ordering::Relaxed)
+    }
+
+    /// Every message discarded, for any reason
+    pub fn total(&self) -> u64 {
+        self.wrong_source()
+            + self.malformed()
+            + self.not_response()
+            + self.wrong_id()
+            + self.wrong_question()
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Parses `bytes` if they hold the response to `query`.
+///
+/// Returns [`None`] after counting the message in `discarded` if they do not, including a message
+/// that matches the query by ID but fails to parse: anyone may send a datagram with a guessed ID,
+/// so it must not keep the real response from being waited for.
+pub(crate) fn match_response(
+    query: &Message,
+    bytes: &[u8],
+    options: &Options,
+    discarded: &Discarded,
+) -> Option<Message> {
+    check_response(query, bytes, options, discarded).unwrap_or_else(|_| {
+        Discarded::count(&discarded.malformed);
+        None
+    })
+}
+
+/// Like [`match_response`], but a message that matches the query by ID and fails to parse is an error,
+/// as it is over TCP, where only one response is read
+fn check_response(
+    query: &Message,
+    bytes: &[u8],
+    options: &Options,
+    discarded: &Discarded,
+) -> message::Result<Option<Message>> {
+    let header = Header::from_bytes(&mut Cursor::new(bytes))?;
+    if !header.flags.is_response() {
+        Discarded::count(&discarded.not_response);
+        return Ok(None);
+    }
+    if header.id != query.header.id {
+        Discarded::count(&discarded.wrong_id);
+        return Ok(None);
+    }
+
+    let resp = Message::from_bytes(&mut Cursor::new(bytes))?;
+    let echoed = resp.questions == query.questions
+        && (!options.exact_case
+            || resp
+                .questions
+                .iter()
+                .zip(&query.questions)
+                .all(|(echo, question)| echo.qname.eq_exact(&question.qname)));
+    if !echoed {
+        Discarded::count(&discarded.wrong_question);
+        return Ok(None);
+    }
+    Ok(Some(resp))
+}
+
+/// The address to bind a socket exchanging messages with `server` to:
+/// any local address of the same family, on a port picked by the system
+fn local_addr_for(server: SocketAddr) -> SocketAddr {
+    match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// Returns a ready-to-use UDP socket connected to the given address, over IPv4 or IPv6 alike
+pub(crate) fn setup_udp_socket_to(
+    dns_server_addr: impl ToSocketAddrs,
+) -> std::io::Result<UdpSocket> {
+    let server = dns_server_addr.to_socket_addrs()?.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to connect to")
+    })?;
+    let udp_sock = UdpSocket::bind(local_addr_for(server))?;
+    udp_sock.connect(server)?;
+    Ok(udp_sock)
+}
+
+/// Sends `query` to `server` as set out by `options`, returning the response
+///
+/// Waiting on the server longer than the timeout fails with [`std::io::ErrorKind::TimedOut`]
+/// or [`std::io::ErrorKind::WouldBlock`], depending on the platform.
+/// Messages that are not the response to `query` are counted in `discarded`.
+pub fn send(
+    query: &Message,
+    server: SocketAddr,
+    options: &Options,
+    discarded: &Discarded,
+) -> message::Result<Message> {
+    match options.protocol {
+        Protocol::Tcp => send_tcp(query, server, options, discarded),
+        Protocol::Udp => {
+            let resp = send_udp(query, server, options, discarded)?;
+            if resp.header.flags.is_truncated() {
+                send_tcp(query, server, options, discarded)
+            } else {
+                Ok(resp)
+            }
+        }
+    }
+}
+
+/// Sends `query` to `server` in a single UDP datagram, returning the response
+///
+/// The response may be truncated, check the TC bit.
+/// Datagrams that are not the response are discarded until the timeout runs out.
+pub fn send_udp(
+    query: &Message,
+    server: SocketAddr,
+    options: &Options,
+    discarded: &Discarded,
+) -> message::Result<Message> {
+    let payload_size = udp_payload_size(query);
+
+    // connection setup
+    let udp_sock = setup_udp_socket_to(server)?;
+
+    // query request
+    udp_sock.send(&query.to_bytes()?)?;
+
+    // get response, ignoring anything that does not answer the query
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut recv_buf = vec![0u8; payload_size];
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+            }
+            udp_sock.set_read_timeout(Some(remaining))?;
+        }
+
+        let (bytes_recv, source) = udp_sock.recv_from(&mut recv_buf)?;
+        if source != server {
+            Discarded::count(&discarded.wrong_source);
+            continue;
+        }
+        if let Some(resp) = match_response(query, &recv_buf[..bytes_recv], options, discarded) {
+            return Ok(resp);
+        }
+    }
+}
+
+/// Sends `query` to `server` over a new TCP connection, returning the response
+///
+/// Only one response is read from the connection, so one that does not answer the query is an error.
+pub fn send_tcp(
+    query: &Message,
+    server: SocketAddr,
+    options: &Options,
+    discarded: &Discarded,
+) -> message::Result<Message> {
+    let timeout = options.timeout;
+    let mut stream = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&server, timeout)?,
+        None => TcpStream::connect(server)?,
+    };
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    stream.write_all(&tcp_frame(query)?)?;
+
+    let mut length = [0u8; 2];
+    stream.read_exact(&mut length)?;
+    let mut recv_buf = vec![0u8; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut recv_buf)?;
+
+    check_response(query, &recv_buf, options, discarded)?.map_or_else(mismatched_tcp_response, Ok)
+}
+
+/// The response to a query sent with `Transport::send_async`
+#[cfg(feature = "tokio")]
+pub type ResponseFuture<'a> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = message::Result<Message>> + Send + 'a>>;
+
+/// Exchanges messages with name servers: sends a query to a server and returns its response
+///
+/// A transport is shared by every resolution of a [`Resolver`](crate::Resolver), possibly on many threads.
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    /// Sends `query` to `server` as set out by `options`, returning the response
+    ///
+    /// Not hearing back in time fails with [`std::io::ErrorKind::TimedOut`] or
+    /// [`std::io::ErrorKind::WouldBlock`].
+    fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message>;
+
+    /// Like [`Transport::send`], but for a tokio runtime, on which the thread must not be blocked.
+    ///
+    /// By default the query is sent with [`Transport::send`] when the future is first polled,
+    /// which suits transports that never wait on the network, like `mock::Mock`.
+    #[cfg(feature = "tokio")]
+    fn send_async<'a>(
+        &'a self,
+        query: &'a Message,
+        server: SocketAddr,
+        options: &'a Options,
+    ) -> ResponseFuture<'a> {
+        Box::pin(async move { self.send(query, server, options) })
+    }
+
+    /// Counts the messages received but discarded for not being the response to a query
+    fn discarded(&self) -> &Discarded;
+}
+
+/// Sends each query over UDP from a fresh socket, repeating it over TCP if the response is truncated,
+/// or only over TCP if [`Options::protocol`] says so, like [`send`]
+#[derive(Debug, Default)]
+pub struct Udp {
+    discarded: Discarded,
+}
+
+impl Udp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the messages received but discarded for not being the response to a query
+    pub fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+}
+
+impl Transport for Udp {
+    fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        send(query, server, options, &self.discarded)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn send_async<'a>(
+        &'a self,
+        query: &'a Message,
+        server: SocketAddr,
+        options: &'a Options,
+    ) -> ResponseFuture<'a> {
+        Box::pin(nonblocking::send(query, server, options, &self.discarded))
+    }
+
+    fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+}
+
+/// Sends each query over a new TCP connection, whatever [`Options::protocol`] says
+#[derive(Debug, Default)]
+pub struct Tcp {
+    discarded: Discarded,
+}
+
+impl Tcp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the messages received but discarded for not being the response to a query
+    pub fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+}
+
+impl Transport for Tcp {
+    fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        send_tcp(query, server, options, &self.discarded)
+    }
+
+    #[cfg(feature = "tokio")]
+    fn send_async<'a>(
+        &'a self,
+        query: &'a Message,
+        server: SocketAddr,
+        options: &'a Options,
+    ) -> ResponseFuture<'a> {
+        Box::pin(nonblocking::send_tcp(
+            query,
+            server,
+            options,
+            &self.discarded,
+        ))
+    }
+
+    fn discarded(&self) -> &Discarded {
+        &self.discarded
+    }
+}
+
+/// Sends queries over a pool of UDP sockets shared by every caller, matching responses back to
+/// the queries waiting on them by socket, ID, source and question.
+///
+/// A query identical to one already in flight to the same server, but for its ID, is not sent again;
+/// its caller waits on the response to the first. The query stays in flight until it is answered
+/// or every caller waiting on it has given up. Each socket is bound on first use, along with a
+/// thread receiving its datagrams, which exits once the multiplexer is dropped.
+/// Servers reached over IPv4 and IPv6 are sent queries from separate pools of the same size.
+///
+/// Long-lived sockets keep their source port, so a pool offers off-path attackers fewer ports to
+/// guess than a fresh socket per query, see
+/// [RFC 5452 section 9.2](https://datatracker.ietf.org/doc/html/rfc5452#section-9.2).
+#[derive(Debug)]
+pub struct Multiplexer {
+    shared: Arc<Shared>,
+    next_socket: AtomicUsize,
+}
+
+/// The state of a [`Multiplexer`] shared with its receiving threads
+#[derive(Debug)]
+struct Shared {
+    /// the IPv4 sockets, followed by as many IPv6 sockets
+    sockets: Vec<Mutex<Option<Arc<UdpSocket>>>>,
+    in_flight: Mutex<InFlightQueries>,
+    discarded: Discarded,
+    closed: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct InFlightQueries {
+    /// by the index of the socket the query was sent from and its ID
+    by_id: HashMap<(usize, u16), Arc<InFlight>>,
+    by_query: HashMap<QueryKey, Arc<InFlight>>,
+}
+
+/// Tells apart queries that may not share a response: by server, by whether the response must echo
+/// the case of the question's name, and by the query as encoded but for its ID, so that queries
+/// differing in the case of the name, in their flags or in EDNS are each sent
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct QueryKey {
+    server: SocketAddr,
+    exact_case: bool,
+    query: Vec<u8>,
+}
+
+/// A query sent by a [`Multiplexer`], waited on by one or more callers
+#[derive(Debug)]
+struct InFlight {
+    /// the query as sent, with an ID unique among those in flight on its socket
+    query: Message,
+    key: QueryKey,
+    socket: usize,
+    options: Options,
+    /// how many callers are waiting on the response, only changed while holding [`Shared::in_flight`]
+    waiters: AtomicUsize,
+    outcome: Mutex<Option<Outcome>>,
+    done: Condvar,
+}
+
+#[derive(Debug, Clone)]
+enum Outcome {
+    Response(Message),
+    Failed(std::io::ErrorKind),
+}
+
+impl Multiplexer {
+    /// How often receiving threads check whether their multiplexer was dropped
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Creates a [`Multiplexer`] sending queries over at most `sockets` sockets (at least one)
+    /// per address family
+    pub fn new(sockets: usize) -> Self {
+        let shared = Shared {
+            sockets: (0..sockets.max(1) * 2).map(|_| Mutex::new(None)).collect(),
+            in_flight: Mutex::default(),
+            discarded: Discarded::default(),
+            closed: AtomicBool::new(false),
+        };
+        Self {
+            shared: Arc::new(shared),
+            next_socket: AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts the datagrams received but discarded for not being the response to a query
+    pub fn discarded(&self) -> &Discarded {
+        &self.shared.discarded
+    }
+
+    /// How many queries are waiting on a response
+    pub fn in_flight(&self) -> usize {
+        self.shared.in_flight().by_id.len()
+    }
+
+    /// Sends `query` to `server` as set out by `options`, returning the response
+    ///
+    /// The response carries the ID of `query`, even if it answers an identical query sent earlier.
+    /// Truncated responses and queries sent over TCP are exchanged over a new connection, like [`send`].
+    pub fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        let discarded = &self.shared.discarded;
+        if options.protocol == Protocol::Tcp {
+            return send_tcp(query, server, options, discarded);
+        }
+
+        let resp = self.send_udp(query, server, options)?;
+        if resp.header.flags.is_truncated() {
+            send_tcp(query, server, options, discarded)
+        } else {
+            Ok(resp)
+        }
+    }
+
+    fn send_udp(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let in_flight = self.join_or_send(query, server, options)?;
+
+        let Some(outcome) = in_flight.wait(deadline) else {
+            self.shared.give_up(&in_flight);
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        };
+
+        match outcome {
+            Outcome::Response(mut resp) => {
+                resp.header.id = query.header.id;
+                Ok(resp)
+            }
+            Outcome::Failed(kind) => Err(std::io::Error::from(kind).into()),
+        }
+    }
+
+    /// Returns the query in flight identical to `query` to `server`, or sends `query` and returns it,
+    /// counting the caller among its waiters
+    fn join_or_send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Arc<InFlight>> {
+        let mut encoded = query.to_bytes()?;
+        // the ID, in the first two octets, differs between otherwise identical queries
+        encoded[..2].fill(0);
+        let key = QueryKey {
+            server,
+            exact_case: options.exact_case,
+            query: encoded,
+        };
+        let mut in_flight = self.shared.in_flight();
+        if let Some(existing) = in_flight.by_query.get(&key) {
+            existing.waiters.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(existing));
+        }
+
+        let per_family = self.shared.sockets.len() / 2;
+        let socket_idx = self.next_socket.fetch_add(1, Ordering::Relaxed) % per_family
+            + if server.is_ipv6() { per_family } else { 0 };
+        let socket = Shared::socket(&self.shared, socket_idx)?;
+
+        let mut rng = rand::thread_rng();
+        let id = std::iter::repeat_with(|| rng.gen())
+            .find(|id| !in_flight.by_id.contains_key(&(socket_idx, *id)))
+            .expect("an ID is free, as a socket never has every ID in flight");
+        let mut query = query.clone();
+        query.header.id = id;
+        let mut query_bytes = key.query.clone();
+        query_bytes[..2].copy_from_slice(&id.to_be_bytes());
+
+        let sent = Arc::new(InFlight {
+            query,
+            key: key.clone(),
+            socket: socket_idx,
+            options: *options,
+            waiters: AtomicUsize::new(1),
+            outcome: Mutex::new(None),
+            done: Condvar::new(),
+        });
+        in_flight.by_id.insert((socket_idx, id), Arc::clone(&sent));
+        in_flight.by_query.insert(key, Arc::clone(&sent));
+        drop(in_flight);
+
+        if let Err(err) = socket.send_to(&query_bytes, server) {
+            self.shared.retire(&sent, Outcome::Failed(err.kind()));
+            return Err(err.into());
+        }
+        Ok(sent)
+    }
+}
+
+impl Transport for Multiplexer {
+    fn send(
+        &self,
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+    ) -> message::Result<Message> {
+        Multiplexer::send(self, query, server, options)
+    }
+
+    /// Sends the query from a fresh socket, like [`Udp`], as the pool's sockets are read by blocking threads
+    #[cfg(feature = "tokio")]
+    fn send_async<'a>(
+        &'a self,
+        query: &'a Message,
+        server: SocketAddr,
+        options: &'a Options,
+    ) -> ResponseFuture<'a> {
+        Box::pin(nonblocking::send(
+            query,
+            server,
+            options,
+            &self.shared.discarded,
+        ))
+    }
+
+    fn discarded(&self) -> &Discarded {
+        &self.shared.discarded
+    }
+}
+
+impl Drop for Multiplexer {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Shared {
+    fn in_flight(&self) -> MutexGuard<'_, InFlightQueries> {
+        // the maps are left consistent even if a holder panicked
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The socket at `idx`, binding it and starting its receiving thread on first use
+    fn socket(shared: &Arc<Shared>, idx: usize) -> std::io::Result<Arc<UdpSocket>> {
+        let mut slot = shared.sockets[idx]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(socket) = &*slot {
+            return Ok(Arc::clone(socket));
+        }
+
+        let local_addr: SocketAddr = if idx < shared.sockets.len() / 2 {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = Arc::new(UdpSocket::bind(local_addr)?);
+        socket.set_read_timeout(Some(Multiplexer::POLL_INTERVAL))?;
+        let (receiver, receiving) = (Arc::clone(shared), Arc::clone(&socket));
+        std::thread::Builder::new()
+            .name("dirt-multiplexer".to_string())
+            .spawn(move || receiver.receive(idx, &receiving))?;
+
+        *slot = Some(Arc::clone(&socket));
+        Ok(socket)
+    }
+
+    /// Dispatches the datagrams arriving on `socket` until the multiplexer is dropped
+    fn receive(&self, idx: usize, socket: &UdpSocket) {
+        let mut recv_buf = vec![0u8; u16::MAX as usize];
+        while !self.closed.load(Ordering::Relaxed) {
+            // timeouts only serve to check whether the multiplexer is still around
+            if let Ok((size, source)) = socket.recv_from(&mut recv_buf) {
+                self.dispatch(idx, &recv_buf[..size], source);
+            }
+        }
+    }
+
+    /// Hands `bytes` to the query they answer, if any
+    fn dispatch(&self, idx: usize, bytes: &[u8], source: SocketAddr) {
+        let Ok(header) = Header::from_bytes(&mut Cursor::new(bytes)) else {
+            Discarded::count(&self.discarded.malformed);
+            return;
+        };
+        let in_flight = self.in_flight().by_id.get(&(idx, header.id)).cloned();
+        let Some(in_flight) = in_flight else {
+            if header.flags.is_response() {
+                Discarded::count(&self.discarded.wrong_id);
+            } else {
+                Discarded::count(&self.discarded.not_response);
+            }
+            return;
+        };
+        if source != in_flight.key.server {
+            Discarded::count(&self.discarded.wrong_source);
+            return;
+        }
+
+        let matched = match_response(&in_flight.query, bytes, &in_flight.options, &self.discarded);
+        if let Some(resp) = matched {
+            self.retire(&in_flight, Outcome::Response(resp));
+        }
+    }
+
+    /// Stops waiting for a response to `in_flight`, waking its waiters with `outcome`
+    fn retire(&self, in_flight: &Arc<InFlight>, outcome: Outcome) {
+        self.in_flight().remove(in_flight);
+        in_flight.finish(outcome);
+    }
+
+    /// Gives up on `in_flight` for one of its waiters, retiring it once the last has given up
+    fn give_up(&self, in_flight: &Arc<InFlight>) {
+        let mut queries = self.in_flight();
+        if in_flight.waiters.fetch_sub(1, Ordering::Relaxed) == 1 {
+            queries.remove(in_flight);
+            drop(queries);
+            in_flight.finish(Outcome::Failed(std::io::ErrorKind::TimedOut));
+        }
+    }
+}
+
+impl InFlightQueries {
+    /// Forgets `in_flight`, leaving any later query under the same keys alone
+    fn remove(&mut self, in_flight: &Arc<InFlight>) {
+        let id_key = (in_flight.socket, in_flight.query.header.id);
+        if self
+            .by_id
+            .get(&id_key)
+            .is_some_and(|entry| Arc::ptr_eq(entry, in_flight))
+        {
+            self.by_id.remove(&id_key);
+        }
+        if self
+            .by_query
+            .get(&in_flight.key)
+            .is_some_and(|entry| Arc::ptr_eq(entry, in_flight))
+        {
+            self.by_query.remove(&in_flight.key);
+        }
+    }
+}
+
+impl InFlight {
+    /// Wakes every waiter with `outcome`, unless the query already had one
+    fn finish(&self, outcome: Outcome) {
+        self.outcome
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(outcome);
+        self.done.notify_all();
+    }
+
+    /// Waits for the outcome of the query, or [`None`] once `deadline` passes
+    fn wait(&self, deadline: Option<Instant>) -> Option<Outcome> {
+        let mut outcome = self.outcome.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(outcome) = &*outcome {
+                return Some(outcome.clone());
+            }
+            outcome = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return None;
+                    }
+                    self.done
+                        .wait_timeout(outcome, remaining)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .done
+                    .wait(outcome)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+}
+
+/// The largest response to `query` that may arrive over UDP
+fn udp_payload_size(query: &Message) -> usize {
+    // without EDNS, responses are limited to 512 octets
+    query
+        .edns()
+        .map_or(Edns::MIN_UDP_PAYLOAD_SIZE, Edns::max_payload_size)
+        .into()
+}
+
+/// Encodes `query` prefixed with its length, as both directions do over TCP
+fn tcp_frame(query: &Message) -> message::Result<Vec<u8>> {
+    let query_bytes = query.to_bytes()?;
+    let length = query_bytes.len();
+    let prefix = u16::try_from(length).map_err(|_| message::Error::TooLong { length })?;
+    let mut framed = Vec::with_capacity(length + 2);
+    framed.extend_from_slice(&prefix.to_be_bytes());
+    framed.extend(query_bytes);
+    Ok(framed)
+}
+
+/// Only one response is read from a TCP connection, so one that does not answer the query is an error
+fn mismatched_tcp_response() -> message::Result<Message> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "response does not match the query",
+    )
+    .into())
+}
+
+/// The exchanges of the parent module on a tokio runtime, waiting on sockets without blocking the thread
+#[cfg(feature = "tokio")]
+pub mod nonblocking {
+    use std::net::SocketAddr;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, UdpSocket},
+        time::Instant,
+    };
+
+    use super::{
+        check_response, local_addr_for, match_response, mismatched_tcp_response, tcp_frame,
+        udp_payload_size, Discarded, Options, Protocol,
+    };
+    use crate::message::{self, Message};
+
+    /// Sends `query` to `server` as set out by `options`, returning the response
+    ///
+    /// Waiting on the server longer than the timeout fails with [`std::io::ErrorKind::TimedOut`].
+    /// Messages that are not the response to `query` are counted in `discarded`.
+    pub async fn send(
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+        discarded: &Discarded,
+    ) -> message::Result<Message> {
+        match options.protocol {
+            Protocol::Tcp => send_tcp(query, server, options, discarded).await,
+            Protocol::Udp => {
+                let resp = send_udp(query, server, options, discarded).await?;
+                if resp.header.flags.is_truncated() {
+                    send_tcp(query, server, options, discarded).await
+                } else {
+                    Ok(resp)
+                }
+            }
+        }
+    }
+
+    /// Sends `query` to `server` in a single UDP datagram, returning the response
+    ///
+    /// The response may be truncated, check the TC bit.
+    /// Datagrams that are not the response are discarded until the timeout runs out.
+    pub async fn send_udp(
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+        discarded: &Discarded,
+    ) -> message::Result<Message> {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        with_deadline(deadline, async {
+            let udp_sock = UdpSocket::bind(local_addr_for(server)).await?;
+            udp_sock.connect(server).await?;
+            udp_sock.send(&query.to_bytes()?).await?;
+
+            let mut recv_buf = vec![0u8; udp_payload_size(query)];
+            loop {
+                let (bytes_recv, source) = udp_sock.recv_from(&mut recv_buf).await?;
+                if source != server {
+                    Discarded::count(&discarded.wrong_source);
+                    continue;
+                }
+                if let Some(resp) =
+                    match_response(query, &recv_buf[..bytes_recv], options, discarded)
+                {
+                    return Ok(resp);
+                }
+            }
+        })
+        .await
+    }
+
+    /// Sends `query` to `server` over a new TCP connection, returning the response
+    ///
+    /// Only one response is read from the connection, so one that does not answer the query is an error.
+    pub async fn send_tcp(
+        query: &Message,
+        server: SocketAddr,
+        options: &Options,
+        discarded: &Discarded,
+    ) -> message::Result<Message> {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        with_deadline(deadline, async {
+            let mut stream = TcpStream::connect(server).await?;
+            stream.write_all(&tcp_frame(query)?).await?;
+
+            let length = stream.read_u16().await?;
+            let mut recv_buf = vec![0u8; length as usize];
+            stream.read_exact(&mut recv_buf).await?;
+
+            check_response(query, &recv_buf, options, discarded)?
+                .map_or_else(mismatched_tcp_response, Ok)
+        })
+        .await
+    }
+
+    /// Runs `exchange`, failing with [`std::io::ErrorKind::TimedOut`] if it is not done by `deadline`
+    async fn with_deadline(
+        deadline: Option<Instant>,
+        exchange: impl std::future::Future<Output = message::Result<Message>>,
+    ) -> message::Result<Message> {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, exchange)
+                .await
+                .unwrap_or_else(|_| Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into())),
+            None => exchange.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::{
+        dname::DomainName,
+        header::{Flags, Header},
+        qclass::QClass,
+        qtype::QType,
+        question::Question,
+        rdata::RData,
+        record::Record,
+        test_util::recv_query,
+    };
+
+    fn query() -> Message {
+        Message {
+            header: Header {
+                id: 0xd17e,
+                flags: Flags::new(),
+                num_questions: 1,
+                num_answers: 0,
+                num_authorities: 0,
+                num_additionals: 0,
+            },
+            questions: vec![Question {
+                qname: DomainName::new("example.com"),
+                qtype: QType::TXT,
+                qclass: QClass::IN,
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+            edns: None,
+        }
+    }
+
+    fn response(query: &Message, truncated: bool) -> Message {
+        let mut resp = query.clone();
+        resp.header.flags = Flags::new().with_response(true).with_truncated(truncated);
+        if !truncated {
+            resp.answers.push(Record {
+                name: DomainName::new("example.com"),
+                qtype: QType::TXT,
+                class: QClass::IN,
+                time_to_live: 60,
+                rdata: RData::TXT(vec![vec![b'x'; 255]; 4]),
+            });
+        }
+        resp
+    }
+
+    /// A complete response small enough for any datagram
+    fn small_response(query: &Message) -> Message {
+        let mut resp = response(query, true);
+        resp.header.flags = resp.header.flags.with_truncated(false);
+        resp
+    }
+
+    #[test]
+    fn truncated_udp_falls_back_to_tcp() -> message::Result<()> {
+        let udp_server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = udp_server.local_addr()?;
+        let tcp_server = TcpListener::bind(server_addr)?;
+
+        let udp_thread = std::thread::spawn(move || -> message::Result<()> {
+            let (query, client) = recv_query(&udp_server)?;
+            udp_server.send_to(&response(&query, true).to_bytes()?, client)?;
+            Ok(())
+        });
+
+        let tcp_thread = std::thread::spawn(move || -> message::Result<()> {
+            let (mut stream, _) = tcp_server.accept()?;
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length)?;
+            let mut buf = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut buf)?;
+            let query = Message::from_bytes(&mut Cursor::new(&buf[..]))?;
+
+            let resp = response(&query, false).to_bytes()?;
+            stream.write_all(&(resp.len() as u16).to_be_bytes())?;
+            stream.write_all(&resp)?;
+            Ok(())
+        });
+
+        let query = query();
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
+        };
+        let resp = send(&query, server_addr, &options, &Discarded::default())?;
+
+        udp_thread.join().unwrap()?;
+        tcp_thread.join().unwrap()?;
+
+        assert!(!resp.header.flags.is_truncated());
+        assert_eq!(resp.answers, response(&query, false).answers);
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_responses_discarded() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        let server_thread = std::thread::spawn(move || -> message::Result<()> {
+            let (query, client) = recv_query(&server)?;
+            // small enough to fit a datagram
+            let resp = response(&query, true);
+
+            let mut wrong_id = resp.clone();
+            wrong_id.header.id = query.header.id.wrapping_add(1);
+            let mut not_response = resp.clone();
+            not_response.header.flags = Flags::new();
+            let mut wrong_question = resp.clone();
+            wrong_question.questions[0].qname = DomainName::new("example.net");
+
+            // junk carrying the query's ID does not end the wait either
+            let mut junk = resp.to_bytes()?;
+            junk.truncate(junk.len() - 1);
+
+            server.send_to(b"\xd1", client)?;
+            server.send_to(&junk, client)?;
+            for msg in [wrong_id, not_response, wrong_question, resp] {
+                server.send_to(&msg.to_bytes()?, client)?;
+            }
+            Ok(())
+        });
+
+        let discarded = Discarded::default();
+        let query = query();
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
+        };
+        let resp = send_udp(&query, server_addr, &options, &discarded)?;
+        server_thread.join().unwrap()?;
+
+        assert_eq!(resp.header.id, query.header.id);
+        assert_eq!(discarded.wrong_id(), 1);
+        assert_eq!(discarded.not_response(), 1);
+        assert_eq!(discarded.wrong_question(), 1);
+        assert_eq!(discarded.malformed(), 2);
+        assert_eq!(discarded.total(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn exact_case_echo_required() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        let server_thread = std::thread::spawn(move || -> message::Result<()> {
+            let (query, client) = recv_query(&server)?;
+            let resp = response(&query, true);
+
+            let mut lowercased = resp.clone();
+            lowercased.questions[0].qname = DomainName::new("example.com");
+
+            for msg in [lowercased, resp] {
+                server.send_to(&msg.to_bytes()?, client)?;
+            }
+            Ok(())
+        });
+
+        let discarded = Discarded::default();
+        let mut query = query();
+        query.questions[0].qname = DomainName::new("eXaMPlE.cOm");
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            exact_case: true,
+            ..Options::default()
+        };
+        let resp = send_udp(&query, server_addr, &options, &discarded)?;
+        server_thread.join().unwrap()?;
+
+        assert!(resp.questions[0].qname.eq_exact(&query.questions[0].qname));
+        assert_eq!(discarded.wrong_question(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn identical_queries_sent_once() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        // answers the first query once no other has arrived for a while
+        let server_thread = std::thread::spawn(move || -> message::Result<usize> {
+            let (query, client) = recv_query(&server)?;
+
+            let mut received = 1;
+            server.set_read_timeout(Some(Duration::from_millis(300)))?;
+            while recv_query(&server).is_ok() {
+                received += 1;
+            }
+            server.send_to(&small_response(&query).to_bytes()?, client)?;
+            Ok(received)
+        });
+
+        let multiplexer = Multiplexer::new(4);
+        let callers = 100;
+        let barrier = std::sync::Barrier::new(callers);
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
+        };
+        std::thread::scope(|scope| {
+            let waiters: Vec<_> = (0..callers as u16)
+                .map(|id| {
+                    let (multiplexer, barrier) = (&multiplexer, &barrier);
+                    scope.spawn(move || {
+                        let mut query = query();
+                        query.header.id = id;
+                        barrier.wait();
+                        multiplexer.send(&query, server_addr, &options)
+                    })
+                })
+                .collect();
+            for (id, waiter) in waiters.into_iter().enumerate() {
+                let resp = waiter.join().unwrap()?;
+                assert_eq!(resp.header.id, id as u16);
+            }
+            message::Result::Ok(())
+        })?;
+
+        assert_eq!(server_thread.join().unwrap()?, 1);
+        assert_eq!(multiplexer.in_flight(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn joined_queries_outlive_their_sender() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        // answers every query once no other has arrived for a while
+        let server_thread = std::thread::spawn(move || -> message::Result<usize> {
+            let mut queries = vec![recv_query(&server)?];
+            server.set_read_timeout(Some(Duration::from_millis(300)))?;
+            while let Ok(query) = recv_query(&server) {
+                queries.push(query);
+            }
+            for (query, client) in &queries {
+                server.send_to(&small_response(query).to_bytes()?, client)?;
+            }
+            Ok(queries.len())
+        });
+
+        let multiplexer = Multiplexer::new(1);
+        let options = |timeout, exact_case| Options {
+            timeout: Some(Duration::from_millis(timeout)),
+            exact_case,
+            ..Options::default()
+        };
+        std::thread::scope(|scope| {
+            let multiplexer = &multiplexer;
+            let sender =
+                scope.spawn(move || multiplexer.send(&query(), server_addr, &options(100, false)));
+            std::thread::sleep(Duration::from_millis(50));
+
+            // joins the query in flight, waiting longer than its sender
+            let joined =
+                scope.spawn(move || multiplexer.send(&query(), server_addr, &options(5000, false)));
+            // must get the case it sent echoed, so it cannot share a response with the others
+            let cased = scope.spawn(move || {
+                let mut query = query();
+                query.questions[0].qname = DomainName::new("ExAmple.com");
+                multiplexer.send(&query, server_addr, &options(5000, true))
+            });
+
+            assert!(sender.join().unwrap().is_err());
+            assert!(joined.join().unwrap().is_ok());
+            let resp = cased.join().unwrap()?;
+            assert_eq!(resp.questions[0].qname.to_string(), "ExAmple.com");
+            message::Result::Ok(())
+        })?;
+
+        assert_eq!(server_thread.join().unwrap()?, 2);
+        assert_eq!(multiplexer.in_flight(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn responses_dispatched_to_their_queries() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        // answers both queries in the reverse order, after a stray response and junk
+        let server_thread = std::thread::spawn(move || -> message::Result<()> {
+            let mut queries = Vec::new();
+            for _ in 0..2 {
+                queries.push(recv_query(&server)?);
+            }
+            let (query, client) = &queries[0];
+            let mut stray = small_response(query);
+            stray.header.id = query.header.id.wrapping_add(1);
+            if stray.header.id == queries[1].0.header.id {
+                stray.header.id = stray.header.id.wrapping_add(1);
+            }
+            server.send_to(&stray.to_bytes()?, *client)?;
+            // junk carrying a query's ID leaves it waiting for the real response
+            let junk = small_response(query).to_bytes()?;
+            server.send_to(&junk[..junk.len() - 1], *client)?;
+            for (query, client) in queries.iter().rev() {
+                server.send_to(&small_response(query).to_bytes()?, *client)?;
+            }
+            Ok(())
+        });
+
+        // a single socket, so that both queries share it
+        let multiplexer = Multiplexer::new(1);
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
+        };
+        let names = ["example.com", "example.net"];
+        std::thread::scope(|scope| {
+            let waiters: Vec<_> = names
+                .map(|name| {
+                    let multiplexer = &multiplexer;
+                    scope.spawn(move || {
+                        let mut query = query();
+                        query.questions[0].qname = DomainName::new(name);
+                        multiplexer.send(&query, server_addr, &options)
+                    })
+                })
+                .into_iter()
+                .collect();
+            for (name, waiter) in names.into_iter().zip(waiters) {
+                let resp = waiter.join().unwrap()?;
+                assert_eq!(resp.questions[0].qname, DomainName::new(name));
+            }
+            message::Result::Ok(())
+        })?;
+        server_thread.join().unwrap()?;
+
+        assert_eq!(multiplexer.discarded().wrong_id(), 1);
+        assert_eq!(multiplexer.discarded().malformed(), 1);
+        assert_eq!(multiplexer.in_flight(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn ipv6_servers_reachable() -> message::Result<()> {
+        let v4_server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let v6_server = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0))?;
+        let servers = [v4_server.local_addr()?, v6_server.local_addr()?];
+
+        // answers a fresh socket's query, then the multiplexer's
+        let server_threads = [v4_server, v6_server].map(|server| {
+            std::thread::spawn(move || -> message::Result<()> {
+                for _ in 0..2 {
+                    let (query, client) = recv_query(&server)?;
+                    server.send_to(&small_response(&query).to_bytes()?, client)?;
+                }
+                Ok(())
+            })
+        });
+
+        let multiplexer = Multiplexer::new(1);
+        let options = Options {
+            timeout: Some(Duration::from_secs(5)),
+            ..Options::default()
+        };
+        for server in servers {
+            let query = query();
+            let resp = send(&query, server, &options, &Discarded::default())?;
+            assert_eq!(resp.header.id, query.header.id);
+            let resp = multiplexer.send(&query, server, &options)?;
+            assert_eq!(resp.header.id, query.header.id);
+        }
+        for server_thread in server_threads {
+            server_thread.join().unwrap()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn nonblocking_discards_and_times_out() -> message::Result<()> {
+        let server = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let server_addr = server.local_addr()?;
+
+        // answers the first query with the wrong ID, then the right one, and ignores the second
+        let server_thread = std::thread::spawn(move || -> message::Result<()> {
+            let (query, client) = recv_query(&server)?;
+            let resp = response(&query, true);
+            let mut wrong_id = resp.clone();
+            wrong_id.header.id = query.header.id.wrapping_add(1);
+            for msg in [wrong_id, resp] {
+                server.send_to(&msg.to_bytes()?, client)?;
+            }
+            recv_query(&server)?;
+            Ok(())
+        });
+
+        let discarded = Discarded::default();
+        let query = query();
+        let options = Options {
+            timeout: Some(Duration::from_millis(200)),
+            ..Options::default()
+        };
+        let resp = nonblocking::send_udp(&query, server_addr, &options, &discarded).await?;
+        assert_eq!(resp.header.id, query.header.id);
+        assert_eq!(discarded.wrong_id(), 1);
+
+        let err = nonblocking::send_udp(&query, server_addr, &options, &discarded)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, message::Error::Io(err) if err.kind() == std::io::ErrorKind::TimedOut),
+            "{err}"
+        );
+        server_thread.join().unwrap()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Checks that reading a message in place as a [`MessageRef`] does not allocate.
+//!
+//! Kept apart from the unit tests, as counting allocations takes over the global allocator
+//! of the whole test binary.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    net::Ipv4Addr,
+};
+
+use dirt::{
+    dname::DomainName,
+    header::{Flags, Header},
+    message::{Message, MessageRef, MsgSection},
+    qclass::QClass,
+    qtype::QType,
+    question::Question,
+    rdata::RData,
+    record::{Edns, Record},
+};
+
+/// Counts the allocations made by the current thread, so that tests running in parallel
+/// do not disturb each other's counts
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn borrowed_message_does_not_allocate() -> Result<(), dirt::message::Error> {
+    let name = DomainName::new("www.example.com");
+    let record = |owner: &str, rdata: RData| Record {
+        name: DomainName::new(owner),
+        qtype: rdata.rtype(),
+        class: QClass::IN,
+        time_to_live: 300,
+        rdata,
+    };
+    let bytes = Message {
+        header: Header {
+            id: 0xbeef,
+            flags: Flags::new().with_response(true),
+            num_questions: 0,
+            num_answers: 0,
+            num_authorities: 0,
+            num_additionals: 0,
+        },
+        questions: vec![Question {
+            qname: name.clone(),
+            qtype: QType::A,
+            qclass: QClass::IN,
+        }],
+        answers: vec![
+            record(
+                "www.example.com",
+                RData::CNAME(DomainName::new("web.example.com")),
+            ),
+            record("web.example.com", RData::A(Ipv4Addr::new(192, 0, 2, 1))),
+        ],
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns: Some(Edns::default()),
+    }
+    .to_bytes()?;
+
+    let mut answer_names = 0;
+    let allocations = allocations_during(|| {
+        let view = MessageRef::from_bytes(&bytes).unwrap();
+        assert!(view.questions().all(|question| question.qname == name));
+        answer_names = view
+            .records(MsgSection::Answers)
+            .filter(|record| record.name == name)
+            .count();
+    });
+    assert_eq!(allocations, 0);
+    assert_eq!(answer_names, 1);
+    Ok(())
+}